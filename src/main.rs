@@ -20,23 +20,38 @@
 )]
 #![allow(clippy::missing_panics_doc)]
 
-use std::ffi::OsStr;
-use std::fmt::Debug;
-use std::path::Path;
-use std::rc::Rc;
-use std::time::Duration;
+use std::borrow::Cow;
+use std::cell::{OnceCell, RefCell};
+use std::collections::{hash_map::Entry, HashMap};
+use std::ffi::{OsStr, OsString};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, SystemTime};
 
 use gio::{prelude::*, Application, DBusInterfaceInfo, DesktopAppInfo, IOErrorEnum};
 use gio::{ApplicationFlags, DBusNodeInfo};
 use glib::{UriFlags, Variant, VariantDict};
 use rusqlite::{OpenFlags, OptionalExtension};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+mod scoring;
+mod xdg;
+
+use scoring::{find_matching_uris, find_matching_uris_tiered, ScoringConfig, WORKSPACE_EXTENSION};
 
 static G_LOG_DOMAIN: &str = "VSCodeSearchProvider";
 
+/// The ID of our application, which is also our bus name.
+static APP_ID: &str = "de.swsnr.VSCodeSearchProvider";
+
 /// The literal XML definition of the interface.
 static SEARCH_PROVIDER2_XML: &str = include_str!("../dbus-1/org.gnome.ShellSearchProvider2.xml");
 
+/// The literal XML definition of our own interfaces.
+static VSCODE_SEARCH_PROVIDER_XML: &str =
+    include_str!("../dbus-1/de.swsnr.VSCodeSearchProvider.xml");
+
 #[derive(Debug, Deserialize)]
 struct WorkspaceEntry {
     #[serde(rename = "configPath")]
@@ -55,23 +70,74 @@ enum StorageOpenedPathsListEntry {
     },
     File {
         #[serde(rename = "fileUri")]
-        #[allow(dead_code)]
         uri: String,
     },
 }
 
 #[derive(Debug, Deserialize, Default)]
 pub struct StorageOpenedPathsList {
+    version: Option<serde_json::Value>,
     entries: Option<Vec<StorageOpenedPathsListEntry>>,
 }
 
+/// The keys under which a wrapping object may hold the list of recently opened paths.
+const WRAPPED_LIST_KEYS: [&str; 2] = ["value", "data"];
+
+/// Get the key under which `value` wraps the list of recently opened paths, if any.
+///
+/// Besides the current shape, tolerate a wrapping object with a version and the
+/// actual list under `value` or `data`, in case Visual Studio Code ever starts
+/// to version this list.
+fn wrapped_list_key(value: &serde_json::Value) -> Option<&'static str> {
+    WRAPPED_LIST_KEYS
+        .into_iter()
+        .find(|key| value.get(key).is_some_and(serde_json::Value::is_object))
+}
+
+/// Get the list of recently opened paths from a stored and decoded `value`.
+///
+/// See [`wrapped_list_key`] for the shapes we know.
+fn unwrap_list(value: &mut serde_json::Value) -> &mut serde_json::Value {
+    match wrapped_list_key(value) {
+        Some(key) => {
+            glib::debug!(
+                "Found recently opened paths list wrapped with version {:?}",
+                value.get("version")
+            );
+            &mut value[key]
+        }
+        None => value,
+    }
+}
+
+/// Decode `value` once more if it is a JSON string.
+///
+/// Some versions of Visual Studio Code store the list of recently opened paths
+/// as a JSON string which contains the JSON of the list, i.e. encoded twice.
+fn decode_double_encoded_json(value: serde_json::Value) -> serde_json::Result<serde_json::Value> {
+    match value {
+        serde_json::Value::String(json) => {
+            glib::debug!("Found recently opened paths list encoded as JSON string");
+            serde_json::from_str(&json)
+        }
+        value => Ok(value),
+    }
+}
+
+/// The key of the list of recently opened paths in the global storage of Visual Studio Code.
+///
+/// See [`CodeVariant::storage_key`].
+const RECENTLY_OPENED_PATHS_LIST_KEY: &str = "history.recentlyOpenedPathsList";
+
+/// Query the list of recently opened paths under `storage_key` from `connection`.
 fn query_recently_opened_path_lists(
     connection: &rusqlite::Connection,
+    storage_key: &str,
 ) -> Result<Option<StorageOpenedPathsList>, glib::Error> {
     connection
         .query_row_and_then(
-            "SELECT value FROM ItemTable WHERE key = 'history.recentlyOpenedPathsList';",
-            [],
+            "SELECT value FROM ItemTable WHERE key = ?1;",
+            [storage_key],
             |row| row.get(0),
         )
         .optional()
@@ -84,14 +150,21 @@ fn query_recently_opened_path_lists(
             )
         })?
         .map(|value| {
-            serde_json::from_value(value).map_err(|error| {
-                glib::Error::new(
-                    IOErrorEnum::InvalidData,
-                    &format!(
-                        "Failed to deserialize recently opened path lists: {error}",
-                    ),
-                )
-            })
+            decode_double_encoded_json(value)
+                .and_then(|mut value| {
+                    serde_json::from_value::<StorageOpenedPathsList>(unwrap_list(&mut value).take())
+                })
+                .inspect(|list| {
+                    if let Some(version) = &list.version {
+                        glib::debug!("Found recently opened paths list with version {version}");
+                    }
+                })
+                .map_err(|error| {
+                    glib::Error::new(
+                        IOErrorEnum::InvalidData,
+                        &format!("Failed to deserialize recently opened path lists: {error}"),
+                    )
+                })
         })
         .transpose()
 }
@@ -164,349 +237,5395 @@ impl DBusMethodCall for SearchProvider2Method {
     }
 }
 
-/// Calculate how well `uri` matches all of the given `terms`.
+/// Strip any query string or fragment from the undecoded `uri`.
+///
+/// Remove everything after the first `?` or `#`, which some remote URIs carry.
+/// Only do this on undecoded URIs: In decoded paths these characters may well
+/// be part of a segment, e.g. `F#-tools` from `F%23-tools`.
+fn strip_query_and_fragment(uri: &str) -> &str {
+    uri.split_once(['?', '#']).map_or(uri, |(before, _)| before)
+}
+
+/// Get the name of the last non-empty segment of `uri_or_path`.
+#[must_use]
+pub fn name_from_uri(uri_or_path: &str) -> Option<&str> {
+    name_suffix_from_uri(uri_or_path, 1)
+}
+
+/// Get the last `depth` non-empty segments of `uri_or_path`.
+///
+/// Like [`name_from_uri`], but include up to `depth` segments, e.g. `src/a` for
+/// `/home/me/src/a/` at depth 2.
+#[must_use]
+pub fn name_suffix_from_uri(uri_or_path: &str, depth: usize) -> Option<&str> {
+    let trimmed = uri_or_path.trim_end_matches('/');
+    let mut start = trimmed.len();
+    for _ in 0..depth {
+        let head = trimmed[..start].trim_end_matches('/');
+        if head.is_empty() {
+            break;
+        }
+        start = head.rfind('/').map_or(0, |index| index + 1);
+    }
+    Some(&trimmed[start..]).filter(|suffix| !suffix.is_empty())
+}
+
+/// Normalize the decoded `path` of a `file://` URI with a Windows drive letter.
 ///
-/// The URI gets scored for each term according to how far to the right it appears in the URI,
-/// under the assumption that the right most part of an URI path is the most specific.
+/// Visual Studio Code on Windows, e.g. via WSL, stores such URIs as
+/// `file:///c%3A/Users/me`, whose path decodes to `/c:/Users/me`.  Remove the
+/// leading slash and upper-case the drive letter, i.e. return `C:/Users/me`.
+/// Return all other paths as they are.
+fn normalize_drive_letter(path: String) -> String {
+    match path.as_bytes() {
+        [b'/', drive, b':'] | [b'/', drive, b':', b'/', ..] if drive.is_ascii_alphabetic() => {
+            format!("{}{}", char::from(drive.to_ascii_uppercase()), &path[2..])
+        }
+        _ => path,
+    }
+}
+
+/// Decode the workspace `uri` for matching and display.
 ///
-/// All matches are done on the lowercase text, i.e. case-insensitive.
+/// For local `file://` URIs return the decoded path, with Windows drive letters
+/// normalized, see [`normalize_drive_letter`], otherwise return the whole
+/// URI with all percent escapes decoded.  Plus signs remain as they are, because
+/// unlike in query strings they do not denote spaces in URI paths.
 ///
-/// Return a positive score if all of `terms` match `uri`.  The higher the score the
-/// better the match, in relation to other matching values.  In and by itself however
-/// the score has no intrinsic meaning.
+/// Return `None` if `uri` is no valid URI, or does not decode to valid UTF-8.
+fn decode_uri(uri: &str) -> Option<String> {
+    let parsed_uri = glib::Uri::parse(uri, UriFlags::NONE).ok()?;
+    if parsed_uri.scheme() == "file" && parsed_uri.host().map_or(true, |host| host.is_empty()) {
+        Some(normalize_drive_letter(parsed_uri.path().into()))
+    } else {
+        let bytes = glib::Uri::unescape_bytes(uri, None).ok()?;
+        std::str::from_utf8(&bytes).ok().map(ToOwned::to_owned)
+    }
+}
+
+/// Remote authorities of Visual Studio Code with a named remote host, and their labels.
 ///
-/// If one term out of `terms` does not match `uri` return a score of 0, regardless
-/// of how well other terms match.
-#[allow(
-    clippy::cast_precision_loss,
-    reason = "terms won't grow so large as to cause issues in f64 conversion"
-)]
-fn score_uri<S: AsRef<str>>(uri: &str, terms: &[S]) -> f64 {
-    let uri = uri.to_lowercase();
-    terms
+/// Visual Studio Code denotes these remote workspaces as
+/// `vscode-remote://<kind>+<name>/<path>`.  For GitHub Codespaces `<kind>` is
+/// `codespaces` and `<name>` the name of the codespace, e.g.
+/// `vscode-remote://codespaces+fuzzy-space-invention-5g4x/workspaces/repo`.
+/// For remote tunnels `<kind>` is `tunnel` and `<name>` the name of the
+/// tunnel, e.g. `vscode-remote://tunnel+my-desktop/home/me/project`.
+const NAMED_REMOTE_AUTHORITIES: &[(&str, &str)] =
+    &[("codespaces", "Codespace"), ("tunnel", "Tunnel")];
+
+/// A workspace on a named remote host, see [`NAMED_REMOTE_AUTHORITIES`].
+struct NamedRemoteWorkspace {
+    /// The label of the kind of remote host, e.g. `Codespace`.
+    label: &'static str,
+    /// The name of the remote host.
+    name: String,
+    /// The decoded path of the workspace on the remote host.
+    path: String,
+}
+
+/// Get the named remote workspace `uri` refers to, if any.
+///
+/// See [`NAMED_REMOTE_AUTHORITIES`] for the remote workspaces we recognize.
+fn named_remote_workspace(uri: &str) -> Option<NamedRemoteWorkspace> {
+    let parsed_uri = glib::Uri::parse(uri, UriFlags::NONE).ok()?;
+    if parsed_uri.scheme() != "vscode-remote" {
+        return None;
+    }
+    let host = parsed_uri.host()?;
+    let (kind, name) = host.split_once('+')?;
+    let (_, label) = NAMED_REMOTE_AUTHORITIES
         .iter()
-        .try_fold(0.0, |score, term| {
-            uri.rfind(&term.as_ref().to_lowercase())
-                // We add 1 to avoid returning zero if the term matches right at the beginning.
-                .map(|index| score + ((index + 1) as f64 / uri.len() as f64))
-        })
-        .unwrap_or(0.0)
+        .find(|(candidate, _)| *candidate == kind)?;
+    Some(NamedRemoteWorkspace {
+        label,
+        name: name.to_owned(),
+        path: parsed_uri.path().into(),
+    })
 }
 
-/// Find all URIs from `uris` which match all of `terms`.
+/// Decode the workspace `uri` for matching.
 ///
-/// Score every URI, and filter out all URIs with a score of 0 or less.
-fn find_matching_uris<I, U, S>(uris: I, terms: &[S]) -> Vec<U>
-where
-    S: AsRef<str> + Debug,
-    U: AsRef<str>,
-    I: IntoIterator<Item = U>,
-{
-    let mut scored = uris
-        .into_iter()
-        .filter_map(|uri| {
-            let decoded_uri = glib::Uri::parse(uri.as_ref(), UriFlags::NONE)
-                .ok()
-                .map(|s| s.to_str());
-            let scored_uri = decoded_uri
-                .as_ref()
-                .map_or_else(|| uri.as_ref(), |s| s.as_str());
-            let score = score_uri(scored_uri, terms);
-            glib::trace!("URI {scored_uri} scores {score} against {terms:?}");
-            if score <= 0.0 {
-                None
-            } else {
-                Some((score, uri))
-            }
-        })
+/// Like [`decode_uri`], but for remote workspaces of Visual Studio Code, i.e.
+/// `vscode-remote://<kind>+<name>/<path>`, return the name of the remote host
+/// as first segment of the path, so that search terms can match the name of
+/// the host like a directory, e.g. the name of a codespace or an SSH host.
+/// Omit the scheme and the kind of remote host, so that terms like `remote` or
+/// `ssh` don't match all remote workspaces of a kind.
+fn decode_uri_for_matching(uri: &str) -> Option<String> {
+    let parsed_uri = glib::Uri::parse(uri, UriFlags::NONE).ok()?;
+    match parsed_uri.host() {
+        Some(host) if parsed_uri.scheme() == "vscode-remote" => {
+            let name = host.split_once('+').map_or(host.as_str(), |(_, name)| name);
+            Some(format!(
+                "{name}/{}",
+                parsed_uri.path().trim_start_matches('/')
+            ))
+        }
+        _ => decode_uri(uri),
+    }
+}
+
+/// Get a human-readable name for the last `segment` of a workspace URI.
+///
+/// Replace the extension of workspace files with a "(Workspace)" suffix.
+fn display_name(segment: &str) -> String {
+    segment
+        .strip_suffix(WORKSPACE_EXTENSION)
+        .map_or_else(|| segment.to_owned(), |name| format!("{name} (Workspace)"))
+}
+
+/// Replace all NUL characters in `text` with the Unicode replacement character.
+///
+/// D-Bus strings must not contain NUL characters, but percent-decoding may
+/// well produce some.
+fn replace_nul(text: String) -> String {
+    if text.contains('\0') {
+        glib::warn!("Replacing NUL characters in {text:?}");
+        text.replace('\0', "\u{FFFD}")
+    } else {
+        text
+    }
+}
+
+/// Get a human-readable name and description for the workspace `uri`.
+///
+/// The name is the last segments of the decoded path, see [`workspace_name`]
+/// and [`display_name`], and the description the whole decoded URI,
+/// see [`decode_uri`].  For named remote workspaces, e.g. codespaces, the
+/// description is the kind and name of the remote host and the path instead,
+/// e.g. `Codespace (<name>): <path>`, see [`named_remote_workspace`].
+///
+/// Replace NUL characters in both, see [`replace_nul`].
+fn name_and_description_of_uri(config: DisplayConfig, uri: &str) -> (String, String) {
+    let (name, description) = name_and_description_of_uri_unsanitized(config, uri);
+    (replace_nul(name), replace_nul(description))
+}
+
+/// Get names and descriptions of all `uris`, see [`name_and_description_of_uri`].
+///
+/// If `config` asks to disambiguate names, show more segments of all URIs with
+/// equal names, one segment at a time, until their names differ or no more
+/// segments are left, like editors do for tabs of files with equal names.
+fn names_and_descriptions_of_uris(config: DisplayConfig, uris: &[&str]) -> Vec<(String, String)> {
+    let mut names_and_descriptions = uris
+        .iter()
+        .map(|uri| name_and_description_of_uri(config, uri))
         .collect::<Vec<_>>();
-    #[allow(
-        clippy::cast_possible_truncation,
-        clippy::as_conversions,
-        reason = "Truncation intended to calculate a coarse ordering score"
-    )]
-    scored.sort_by_key(|(score, _)| -((score * 1000.0) as i64));
-    scored.into_iter().map(|(_, uri)| uri).collect::<Vec<_>>()
+    if !config.disambiguate_names {
+        return names_and_descriptions;
+    }
+    let mut depths = vec![config.name_depth; uris.len()];
+    loop {
+        let mut counts = HashMap::<String, usize>::new();
+        for (name, _) in &names_and_descriptions {
+            *counts.entry(name.clone()).or_default() += 1;
+        }
+        let mut changed = false;
+        for (index, uri) in uris.iter().enumerate() {
+            if 1 < counts[&names_and_descriptions[index].0] {
+                let deeper_config = DisplayConfig {
+                    name_depth: depths[index] + 1,
+                    ..config
+                };
+                let deeper = name_and_description_of_uri(deeper_config, uri);
+                if deeper.0 != names_and_descriptions[index].0 {
+                    depths[index] += 1;
+                    names_and_descriptions[index] = deeper;
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    names_and_descriptions
 }
 
-#[must_use]
-pub fn name_from_uri(uri_or_path: &str) -> Option<&str> {
-    uri_or_path.split('/').filter(|seg| !seg.is_empty()).last()
+/// Like [`name_and_description_of_uri`], but without replacing NUL characters.
+fn name_and_description_of_uri_unsanitized(config: DisplayConfig, uri: &str) -> (String, String) {
+    if let Some(remote) = named_remote_workspace(uri) {
+        let name = display_name(
+            name_suffix_from_uri(&remote.path, config.name_depth).unwrap_or(&remote.name),
+        );
+        let description = format!("{} ({}): {}", remote.label, remote.name, remote.path);
+        (name, description)
+    } else if let Some(decoded_uri) = decode_uri(uri) {
+        (display_name(&workspace_name(config, uri)), decoded_uri)
+    } else {
+        glib::warn!("Failed to decode {uri} as URI");
+        let name = percent_decode_name(
+            name_suffix_from_uri(strip_query_and_fragment(uri), config.name_depth).unwrap_or(uri),
+        );
+        (display_name(&name), uri.to_owned())
+    }
 }
 
-#[derive(Debug, Variant)]
-struct StartTransientUnitParameters {
-    name: String,
-    mode: String,
-    properties: Vec<(String, Variant)>,
-    aux: Vec<(String, Vec<(String, Variant)>)>,
+/// Decode all percent escapes in the `name` of a workspace, taken from its URI.
+///
+/// We only take names directly from URIs if they fail to decode as a whole,
+/// but their name segments may well decode on their own, e.g. if the URI has
+/// invalid escapes elsewhere.  Return `name` as is if it does not decode to
+/// valid UTF-8.
+fn percent_decode_name(name: &str) -> String {
+    glib::Uri::unescape_bytes(name, None)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes.to_vec()).ok())
+        .unwrap_or_else(|| name.to_owned())
 }
 
-struct SearchProvider {
-    app: Application,
-    code_app_info: DesktopAppInfo,
-    workspaces: Vec<String>,
+/// Get the name of the workspace `uri`.
+///
+/// Take the last segments of the decoded path of `uri`, per the name depth of
+/// `config`.  For root paths fall back to the host of `uri`, e.g. for remote
+/// roots, or to `/`.  If `uri` fails to parse, decode the last segments of
+/// `uri` on their own, see [`percent_decode_name`].
+fn workspace_name(config: DisplayConfig, uri: &str) -> String {
+    let Ok(parsed_uri) = glib::Uri::parse(uri, UriFlags::NONE) else {
+        return percent_decode_name(
+            name_suffix_from_uri(strip_query_and_fragment(uri), config.name_depth).unwrap_or(uri),
+        );
+    };
+    let path = parsed_uri.path();
+    name_suffix_from_uri(&path, config.name_depth).map_or_else(
+        || {
+            parsed_uri
+                .host()
+                .filter(|host| !host.is_empty())
+                .map_or_else(|| "/".to_owned(), Into::into)
+        },
+        ToOwned::to_owned,
+    )
 }
 
-impl SearchProvider {
-    fn new(app: Application, code_app: DesktopAppInfo, workspaces: Vec<String>) -> Self {
-        Self {
-            app,
-            code_app_info: code_app,
-            workspaces,
+/// Whether the environment variable `name` is set to `1`.
+fn environment_flag(name: &str) -> bool {
+    std::env::var_os(name).is_some_and(|value| value == "1")
+}
+
+/// Read a positive number from the environment variable `name`.
+///
+/// Return `None` if `name` is unset, or ignore its value and return `None` if it
+/// is no positive number.
+fn positive_number_from_environment(name: &str) -> Option<usize> {
+    let value = std::env::var(name).ok()?;
+    match value.parse::<usize>() {
+        Ok(number) if 0 < number => Some(number),
+        _ => {
+            glib::warn!("Ignoring invalid ${name} {value}, expected a positive number");
+            None
         }
     }
+}
 
-    /// Launch the given `uri`, if any, or launch the app directly.
+/// The result ID of the result to launch the app, see [`DisplayConfig::launch_result`].
+const LAUNCH_APP_RESULT_ID: &str = "app://launch";
+
+/// The result ID of the result which tells that workspaces failed to load.
+///
+/// See [`ProviderConfig::error_result`].
+const LOAD_ERROR_RESULT_ID: &str = "app://load-error";
+
+/// Whether `id` identifies a workspace, as opposed to a result of our own.
+fn is_workspace_result_id(id: &str) -> bool {
+    id != LAUNCH_APP_RESULT_ID && id != LOAD_ERROR_RESULT_ID
+}
+
+/// Configuration for displaying results.
+#[derive(Debug, Clone, Copy)]
+struct DisplayConfig {
+    /// How many trailing segments of a workspace URI to show as its name.
+    name_depth: usize,
+    /// Whether to return a result to launch the app if no workspace matches.
+    launch_result: bool,
+    /// Whether to tell apart results with equal names by more segments.
     ///
-    /// Launch the uri with this code via `gio launch` wrapped in `systemd-run`,
-    /// to make damn sure that Visual Studio Code gets its own scope.
+    /// See [`names_and_descriptions_of_uris`].
+    disambiguate_names: bool,
+    /// What to show in descriptions of workspace results.
+    description: DescriptionConfig,
+    /// How many characters of names of workspace results to show at most.
     ///
-    /// We cannot launch the desktop app file directly, e.g. with `launch_uris`,
-    /// and the move the new process to a separate scope using sytemd's D-Bus
-    /// API
-    /// because vscode aggressively forks into background so fast, that we will
-    /// have lost track of its forked children before we get a chance to move
-    /// the whole process tree to a new scope.  This effectively means that the
-    /// actual Visual Studio Code process which shows the window then remains a child of our
-    /// own service scope, and lives and dies with the process of this search
-    /// provider service.  And since we auto-quit our service after a few idle
-    /// minutes we'd take down open Visual Studio Code windows with us.
+    /// See [`truncate_end`].
+    max_name_length: Option<usize>,
+    /// Whether to add the text to copy to workspace results.
     ///
-    /// Since we can't get this down race-free via Gio/GLib itself, spawn a new
-    /// scope first with systemd-run and then spawn the app in with gio launch.
-    async fn launch_uri(&self, uri: Option<&str>) -> Result<(), glib::Error> {
-        let app_desktop_file = self.code_app_info.filename().unwrap();
-        let mut command = vec![
-            OsStr::new("/usr/bin/systemd-run"),
-            OsStr::new("--user"),
-            OsStr::new("--scope"),
-            OsStr::new("--same-dir"),
-            OsStr::new("/usr/bin/gio"),
-            OsStr::new("launch"),
-            OsStr::new(&app_desktop_file),
-        ];
-        command.extend_from_slice(uri.map(OsStr::new).as_slice());
-        glib::info!("Launching command {:?}", command);
-        let process = gio::Subprocess::newv(command.as_slice(), gio::SubprocessFlags::NONE)?;
-        process.wait_future().await?;
-        glib::info!("Command {:?} finished", command);
-        Ok(())
+    /// See [`clipboard_text_of_uri`].
+    clipboard_text: bool,
+}
+
+/// Get the text to copy for the workspace `uri`.
+///
+/// Return the local path of `uri`, or `uri` itself if it has no local path,
+/// e.g. for remote workspaces.
+fn clipboard_text_of_uri(uri: &str) -> String {
+    gio::File::for_uri(uri).path().map_or_else(
+        || uri.to_owned(),
+        |path| path.to_string_lossy().into_owned(),
+    )
+}
+
+/// Truncate `text` to at most `max_length` characters, with an ellipsis at the end.
+fn truncate_end(text: &str, max_length: usize) -> String {
+    if text.chars().count() <= max_length {
+        text.to_owned()
+    } else {
+        let kept = text.chars().take(max_length.saturating_sub(1));
+        kept.chain(std::iter::once('…')).collect()
     }
+}
 
-    /// Handle the given search provider method `call`.
+/// Truncate `text` to at most `max_length` characters, with an ellipsis in the middle.
+///
+/// Keep the start and the end of `text`, since for paths the start tells the
+/// location and the end the workspace itself; keep one more character at the
+/// end if `max_length` does not split evenly.
+fn truncate_middle(text: &str, max_length: usize) -> String {
+    let length = text.chars().count();
+    if length <= max_length {
+        return text.to_owned();
+    }
+    let kept = max_length.saturating_sub(1);
+    let head = kept / 2;
+    let tail = kept - head;
+    text.chars()
+        .take(head)
+        .chain(std::iter::once('…'))
+        .chain(text.chars().skip(length - tail))
+        .collect()
+}
+
+/// What to show in descriptions of workspace results.
+#[derive(Debug, Clone, Copy)]
+struct DescriptionConfig {
+    /// Whether to show the path of workspaces, see [`name_and_description_of_uri`].
+    path: bool,
+    /// Whether to show what workspaces are, see [`WorkspaceKind`].
+    kind: bool,
+    /// How many characters of the path of workspaces to show at most.
     ///
-    /// Perform any side effects triggered by the call and return the appropriate
-    /// result.
-    async fn handle_call(
-        &self,
-        call: SearchProvider2Method,
-    ) -> Result<Option<Variant>, glib::Error> {
-        // Hold on to the application while we're processing a DBus call.
-        let _guard = self.app.hold();
-        match call {
-            SearchProvider2Method::GetInitialResultSet(GetInitialResultSet(terms)) => {
-                glib::debug!("Searching for terms {terms:?}");
-                Ok(Some(
-                    find_matching_uris(&self.workspaces, terms.as_slice()).into(),
-                ))
-            }
-            SearchProvider2Method::GetSubsearchResultSet(GetSubsearchResultSet(
-                previous_results,
-                terms,
-            )) => {
-                glib::debug!(
-                    "Searching for terms {terms:?} in {} previous results",
-                    previous_results.len()
-                );
-                Ok(Some(
-                    find_matching_uris(previous_results, terms.as_slice()).into(),
-                ))
-            }
-            SearchProvider2Method::GetResultMetas(GetResultMetas(identifiers)) => {
-                glib::debug!("Get metadata for {identifiers:?}");
-                let metas: Vec<VariantDict> = identifiers
-                    .into_iter()
-                    .map(|uri| {
-                        let metas = VariantDict::new(None);
-                        metas.insert("id", uri.as_str());
-                        match glib::Uri::parse(&uri, UriFlags::NONE) {
-                            Ok(parsed_uri) => {
-                                metas.insert(
-                                    "name",
-                                    name_from_uri(parsed_uri.path().as_str())
-                                        .unwrap_or(uri.as_str()),
-                                );
-                                match parsed_uri.scheme().as_str() {
-                                    "file:" if parsed_uri.host().is_none() => {
-                                        metas.insert("description", parsed_uri.path().as_str());
-                                    }
-                                    _ => {
-                                        metas.insert("description", parsed_uri.to_str().as_str());
-                                    }
-                                };
-                            }
-                            Err(error) => {
-                                glib::warn!("Failed to parse {uri} as URI: {error}");
-                                metas.insert("name", name_from_uri(&uri).unwrap_or(uri.as_str()));
-                                metas.insert("description", uri.as_str());
-                            }
-                        }
-                        if let Some(app_icon) =
-                            self.code_app_info.icon().and_then(|icon| icon.serialize())
-                        {
-                            metas.insert("icon", app_icon);
-                        }
-                        metas
-                    })
-                    .collect::<Vec<_>>();
-                Ok(Some(metas.into()))
-            }
-            SearchProvider2Method::ActivateResult(ActivateResult(identifier, _, _)) => {
-                glib::info!(
-                    "Launching application {} with URI {identifier}",
-                    self.code_app_info.id().unwrap()
-                );
-                self.launch_uri(Some(identifier.as_ref())).await?;
-                Ok(None)
-            }
-            SearchProvider2Method::LaunchSearch(_) => {
-                glib::info!(
-                    "Launching application {} directly",
-                    self.code_app_info.id().unwrap()
-                );
-                self.launch_uri(None).await?;
-                Ok(None)
-            }
+    /// See [`truncate_middle`].
+    max_length: Option<usize>,
+}
+
+impl DescriptionConfig {
+    /// Describe the workspace `uri` whose path `description` we got.
+    fn describe(self, uri: &str, description: String) -> String {
+        let description = match (self.path, self.max_length) {
+            (false, _) => String::new(),
+            (true, Some(max_length)) => truncate_middle(&description, max_length),
+            (true, None) => description,
+        };
+        if !self.kind {
+            description
+        } else if description.is_empty() {
+            WorkspaceKind::of_uri(uri).label().to_owned()
+        } else {
+            format!("{} · {description}", WorkspaceKind::of_uri(uri).label())
         }
     }
+}
 
-    /// Register this search provider under `object_path` on a D-Bus `connection`.
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            name_depth: 1,
+            launch_result: false,
+            disambiguate_names: false,
+            description: DescriptionConfig {
+                path: true,
+                kind: false,
+                max_length: None,
+            },
+            max_name_length: None,
+            clipboard_text: false,
+        }
+    }
+}
+
+impl DisplayConfig {
+    /// Read the display configuration from the environment.
     ///
-    /// Consume the search provider, as it gets moved into the callback closure for
-    /// D-Bus invocations.
-    fn register(
-        self,
-        connection: &gio::DBusConnection,
-        object_path: &str,
-        interface_info: &DBusInterfaceInfo,
-    ) -> Result<gio::RegistrationId, glib::Error> {
-        let search_provider = Rc::new(self);
-        connection
-            .register_object(object_path, interface_info)
-            .typed_method_call::<SearchProvider2Method>()
-            .invoke_and_return_future_local(move |_, _, call| {
-                let search_provider = search_provider.clone();
-                async move { search_provider.handle_call(call).await }
-            })
-            .build()
+    /// `$VSCODE_SEARCH_PROVIDER_NAME_DEPTH` sets the name depth; ignore invalid
+    /// values and fall back to the default.  `$VSCODE_SEARCH_PROVIDER_LAUNCH_RESULT=1`
+    /// enables the result to launch the app, `$VSCODE_SEARCH_PROVIDER_DISAMBIGUATE_NAMES=1`
+    /// tells apart results with equal names, `$VSCODE_SEARCH_PROVIDER_COMPACT=1`
+    /// omits descriptions of workspaces, and `$VSCODE_SEARCH_PROVIDER_SHOW_KIND=1`
+    /// adds the kind of workspaces to their descriptions.
+    ///
+    /// `$VSCODE_SEARCH_PROVIDER_MAX_NAME_LENGTH` and
+    /// `$VSCODE_SEARCH_PROVIDER_MAX_DESCRIPTION_LENGTH` limit the length of names
+    /// and paths of workspaces.
+    ///
+    /// `$VSCODE_SEARCH_PROVIDER_CLIPBOARD_TEXT=1` adds the text to copy to
+    /// workspace results.
+    fn from_environment() -> Self {
+        Self {
+            clipboard_text: environment_flag("VSCODE_SEARCH_PROVIDER_CLIPBOARD_TEXT"),
+            name_depth: positive_number_from_environment("VSCODE_SEARCH_PROVIDER_NAME_DEPTH")
+                .unwrap_or(Self::default().name_depth),
+            launch_result: environment_flag("VSCODE_SEARCH_PROVIDER_LAUNCH_RESULT"),
+            disambiguate_names: environment_flag("VSCODE_SEARCH_PROVIDER_DISAMBIGUATE_NAMES"),
+            description: DescriptionConfig {
+                path: !environment_flag("VSCODE_SEARCH_PROVIDER_COMPACT"),
+                kind: environment_flag("VSCODE_SEARCH_PROVIDER_SHOW_KIND"),
+                max_length: positive_number_from_environment(
+                    "VSCODE_SEARCH_PROVIDER_MAX_DESCRIPTION_LENGTH",
+                ),
+            },
+            max_name_length: positive_number_from_environment(
+                "VSCODE_SEARCH_PROVIDER_MAX_NAME_LENGTH",
+            ),
+        }
     }
 }
 
-/// Load workspaces from the given connection, and return all workspace URIs.
-fn load_workspaces(connection: &rusqlite::Connection) -> Result<Vec<String>, glib::Error> {
-    Ok(query_recently_opened_path_lists(connection)?
-        .unwrap_or_default()
-        .entries
-        .unwrap_or_default()
-        .into_iter()
-        .filter_map(|entry| match entry {
-            StorageOpenedPathsListEntry::Workspace { workspace } => Some(workspace.config_path),
-            StorageOpenedPathsListEntry::Folder { uri } => Some(uri),
-            StorageOpenedPathsListEntry::File { .. } => None,
-        })
-        .collect())
+/// The configuration of a search provider.
+#[derive(Debug, Clone, Copy, Default)]
+struct ProviderConfig {
+    scoring: ScoringConfig,
+    launch: LaunchConfig,
+    display: DisplayConfig,
+    /// Whether to keep workspaces in a persistent cache, see [`CachedWorkspaces`].
+    cache: bool,
+    /// Whether to serve a search provider even if its workspaces fail to load.
+    ///
+    /// The search provider then returns a single result which tells why
+    /// workspaces are unavailable, see [`LOAD_ERROR_RESULT_ID`].
+    error_result: bool,
+    /// Whether to match workspace files by the declared names of their folders.
+    ///
+    /// See [`Workspaces::new`].
+    workspace_folder_names: bool,
 }
 
-fn open_connection<P: AsRef<Path>>(db_path: P) -> Result<rusqlite::Connection, glib::Error> {
-    let flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
-    rusqlite::Connection::open_with_flags(db_path.as_ref(), flags).map_err(|error| {
-        glib::Error::new(
-            IOErrorEnum::Failed,
-            &format!(
-                "Failed to open connection to {}: {error}",
-                db_path.as_ref().display()
+impl ProviderConfig {
+    /// Read the configuration from the environment.
+    fn from_environment() -> Self {
+        Self {
+            scoring: ScoringConfig::from_environment(),
+            launch: LaunchConfig::from_environment(),
+            display: DisplayConfig::from_environment(),
+            cache: environment_flag("VSCODE_SEARCH_PROVIDER_CACHE"),
+            error_result: environment_flag("VSCODE_SEARCH_PROVIDER_ERROR_RESULT"),
+            workspace_folder_names: environment_flag(
+                "VSCODE_SEARCH_PROVIDER_WORKSPACE_FOLDER_NAMES",
             ),
-        )
-    })
+        }
+    }
 }
 
-fn startup(app: &gio::Application) {
-    // Hold on to the application during startup, to avoid early exit.
-    let _guard = app.hold();
+/// Configuration for launching apps.
+#[derive(Debug, Clone, Copy, Default)]
+struct LaunchConfig {
+    /// Only log and record the command to launch, instead of running it.
+    dry_run: bool,
+    /// Launch apps directly, instead of in a new systemd scope.
+    ///
+    /// Apps then run in the scope of this service, so stopping the service may
+    /// stop all apps it launched.
+    no_scope: bool,
+    /// Activate apps with `DBusActivatable=true` in their desktop entry over D-Bus.
+    ///
+    /// See [`SearchProvider::activate_over_dbus`].
+    dbus_activation: bool,
+}
 
-    let providers = [
-        // The standard Arch Linux code package from community
-        ("code-oss.desktop", "Code - OSS"),
-        // The standard codium package on Linux from here: https://github.com/VSCodium/vscodium.
-        // Should work for most Linux distributions packaged from here.
-        ("codium.desktop", "VSCodium"),
-        // The official install packages from https://code.visualstudio.com/download
-        ("code.desktop", "Code"),
-    ];
+impl LaunchConfig {
+    /// Read the launch configuration from the environment.
+    ///
+    /// `$VSCODE_SEARCH_PROVIDER_DRY_RUN=1` enables dry-run mode,
+    /// `$VSCODE_SEARCH_PROVIDER_NO_SCOPE=1` disables systemd scopes, and
+    /// `$VSCODE_SEARCH_PROVIDER_DBUS_ACTIVATION=1` enables D-Bus activation.
+    fn from_environment() -> Self {
+        Self {
+            dry_run: environment_flag("VSCODE_SEARCH_PROVIDER_DRY_RUN"),
+            no_scope: environment_flag("VSCODE_SEARCH_PROVIDER_NO_SCOPE"),
+            dbus_activation: environment_flag("VSCODE_SEARCH_PROVIDER_DBUS_ACTIVATION"),
+        }
+    }
+}
 
-    let interface = DBusNodeInfo::for_xml(SEARCH_PROVIDER2_XML)
-        .unwrap()
-        .lookup_interface("org.gnome.Shell.SearchProvider2")
-        .unwrap();
-    let user_config_dir = glib::user_config_dir();
+/// Get the object path of the D-Bus activatable app with the given `app_id`.
+///
+/// Per the desktop entry specification, replace `.` with `/` and `-` with `_`,
+/// e.g. `/org/example/My_App` for `org.example.My-App`.
+fn application_object_path(app_id: &str) -> String {
+    format!("/{}", app_id.replace('.', "/").replace('-', "_"))
+}
 
+/// Whether to activate `app_info` over D-Bus, per `config`.
+///
+/// See [`LaunchConfig::dbus_activation`].
+fn uses_dbus_activation(config: LaunchConfig, app_info: &DesktopAppInfo) -> bool {
+    config.dbus_activation && app_info.boolean("DBusActivatable")
+}
+
+/// Get the method and parameters to activate an app over `org.freedesktop.Application`.
+///
+/// Call `Open(as uris, a{sv} platform_data)` to open `uri`, if any, or
+/// `Activate(a{sv} platform_data)` to just start the app.
+fn application_activation_call(uri: Option<&str>) -> (&'static str, Variant) {
+    let platform_data = HashMap::<String, Variant>::new();
+    match uri {
+        Some(uri) => ("Open", (vec![uri], platform_data).to_variant()),
+        None => ("Activate", (platform_data,).to_variant()),
+    }
+}
+
+/// Escape `s` for use in a systemd unit name, like `systemd-escape` does.
+fn escape_unit_name(s: &str) -> String {
+    s.bytes()
+        .enumerate()
+        .map(|(index, byte)| match byte {
+            b'/' => "-".to_owned(),
+            b'.' if index == 0 => format!("\\x{byte:02x}"),
+            b':' | b'_' | b'.' => char::from(byte).to_string(),
+            _ if byte.is_ascii_alphanumeric() => char::from(byte).to_string(),
+            _ => format!("\\x{byte:02x}"),
+        })
+        .collect()
+}
+
+/// Get a new name for a scope of the app with the given `app_id`.
+///
+/// Follow the systemd naming convention for application scopes, and use 64
+/// random bits to make collisions with the scope of an earlier launch
+/// practically impossible.
+fn scope_unit_name(app_id: &str) -> String {
+    format!(
+        "app-gnome-{}-{:016x}.scope",
+        escape_unit_name(app_id),
+        (u64::from(glib::random_int()) << 32) | u64::from(glib::random_int())
+    )
+}
+
+/// Get unit name patterns for all application scopes of the app with `app_id`.
+///
+/// Follow the systemd naming convention for application scopes, with and
+/// without a launcher, i.e. `app-<app_id>-<random>.scope` and
+/// `app-<launcher>-<app_id>-<random>.scope`.  This finds instances started by
+/// GNOME Shell as well as instances launched by this provider.
+fn application_scope_patterns(app_id: &str) -> Vec<String> {
+    let app_id = escape_unit_name(app_id);
+    vec![
+        format!("app-{app_id}-*.scope"),
+        format!("app-*-{app_id}-*.scope"),
+    ]
+}
+
+/// Whether systemd has any unit in any of `states` whose name matches any of `patterns`.
+///
+/// With no `states` consider units in all states.
+fn has_units_matching(
+    connection: &gio::DBusConnection,
+    states: &[&str],
+    patterns: Vec<String>,
+) -> Result<bool, glib::Error> {
+    let reply = connection.call_sync(
+        Some("org.freedesktop.systemd1"),
+        "/org/freedesktop/systemd1",
+        "org.freedesktop.systemd1.Manager",
+        "ListUnitsByPatterns",
+        Some(&(states, patterns).to_variant()),
+        Some(glib::VariantTy::new("(a(ssssssouso))").unwrap()),
+        gio::DBusCallFlags::NONE,
+        1000,
+        gio::Cancellable::NONE,
+    )?;
+    Ok(reply.child_value(0).n_children() > 0)
+}
+
+#[derive(Debug, Variant)]
+struct StartTransientUnitParameters {
+    name: String,
+    mode: String,
+    properties: Vec<(String, Variant)>,
+    aux: Vec<(String, Vec<(String, Variant)>)>,
+}
+
+/// Get the canonical form of `uri`.
+///
+/// Parse `uri` and serialize it again, so that equivalent URIs with different
+/// percent escapes, e.g. `%7E` instead of `~`, have the same canonical form.
+/// Remove a single trailing slash from paths other than the root, since Visual
+/// Studio Code stores the same folder with and without trailing slash.  Return
+/// `uri` as is if it's no valid URI.
+fn canonical_uri(uri: &str) -> String {
+    let Ok(parsed_uri) = glib::Uri::parse(uri, UriFlags::NONE) else {
+        return uri.to_owned();
+    };
+    let mut canonical: String = parsed_uri.to_str().into();
+    let path = parsed_uri.path();
+    if parsed_uri.query().is_none()
+        && parsed_uri.fragment().is_none()
+        && path != "/"
+        && path.ends_with('/')
+    {
+        canonical.pop();
+    }
+    canonical
+}
+
+/// A workspace of a search provider.
+struct Workspace {
+    /// The URI of this workspace as stored by Visual Studio Code, for launching.
+    uri: String,
+    /// The decoded URI, for matching, see [`decode_uri_for_matching`].
+    decoded_uri: String,
+    /// Decoded paths of the named folders of this workspace, for matching.
+    ///
+    /// See [`ProviderConfig::workspace_folder_names`].
+    decoded_folder_names: Vec<String>,
+}
+
+/// A workspace file of Visual Studio Code, i.e. a `.code-workspace` file.
+#[derive(Debug, Deserialize)]
+struct WorkspaceFile {
+    #[serde(default)]
+    folders: Vec<WorkspaceFileFolder>,
+}
+
+/// A folder in a workspace file.
+#[derive(Debug, Deserialize)]
+struct WorkspaceFileFolder {
+    /// The name of the folder, if the workspace file declares any.
+    name: Option<String>,
+}
+
+/// Read the declared names of all folders in the local workspace file at `uri`.
+///
+/// Return no names if `uri` is no local workspace file, or if it fails to read
+/// or to parse.  Visual Studio Code permits comments in workspace files, which
+/// we can't parse.
+fn workspace_folder_names(uri: &str) -> Vec<String> {
+    if !uri.ends_with(WORKSPACE_EXTENSION) {
+        return Vec::new();
+    }
+    let Some(path) = gio::File::for_uri(uri).path() else {
+        return Vec::new();
+    };
+    let result = std::fs::read(&path)
+        .map_err(|error| error.to_string())
+        .and_then(|contents| {
+            serde_json::from_slice::<WorkspaceFile>(&contents).map_err(|error| error.to_string())
+        });
+    match result {
+        Ok(file) => file
+            .folders
+            .into_iter()
+            .filter_map(|folder| folder.name)
+            .filter(|name| !name.is_empty())
+            .collect(),
+        Err(error) => {
+            glib::debug!("Not matching folder names of {}: {error}", path.display());
+            Vec::new()
+        }
+    }
+}
+
+/// All workspaces of a search provider, as loaded from its database.
+struct Workspaces {
+    /// The IDs of all workspaces, in the order Visual Studio Code returned them.
+    ///
+    /// Our result IDs are the canonical workspace URIs, see [`canonical_uri`], so
+    /// that equivalent URIs give the same result.
+    ids: Vec<String>,
+    /// All workspaces, by their ID.
+    by_id: HashMap<String, Workspace>,
+    /// The stamps of all databases when we loaded the workspaces.
+    database_stamps: Vec<Option<FileStamp>>,
+}
+
+impl Workspaces {
+    /// Create workspaces from the given workspace `uris`, loaded from databases
+    /// with the given `database_stamps`.
+    ///
+    /// If `folder_names` is set, read the names of folders of all local
+    /// workspace files, see [`workspace_folder_names`], and match them like
+    /// directories next to the workspace file.
+    fn new(uris: Vec<String>, database_stamps: Vec<Option<FileStamp>>, folder_names: bool) -> Self {
+        let mut workspaces = Self {
+            ids: Vec::with_capacity(uris.len()),
+            by_id: HashMap::with_capacity(uris.len()),
+            database_stamps,
+        };
+        workspaces.extend(uris, folder_names);
+        workspaces
+    }
+
+    /// Add the given workspace `uris` after all current workspaces.
+    ///
+    /// Skip URIs equivalent to any current workspace; see [`Workspaces::new`]
+    /// for `folder_names`.
+    fn extend(&mut self, uris: Vec<String>, folder_names: bool) {
+        for uri in uris {
+            // We can't send URIs with NUL characters over D-Bus, so we can't
+            // offer them as results either.
+            if uri.contains('\0') {
+                glib::warn!("Skipping workspace URI with NUL character: {uri:?}");
+                continue;
+            }
+            let id = canonical_uri(&uri);
+            // Only keep the first, i.e. most recent, of equivalent URIs
+            if let Entry::Vacant(entry) = self.by_id.entry(id) {
+                // Decode the canonical URI, without trailing slash, so that
+                // matches in the name do not count as matches in the parent.
+                let decoded_uri =
+                    decode_uri_for_matching(entry.key()).unwrap_or_else(|| entry.key().clone());
+                let decoded_folder_names = if folder_names {
+                    let parent = decoded_uri
+                        .rsplit_once('/')
+                        .map_or("", |(parent, _)| parent);
+                    workspace_folder_names(&uri)
+                        .into_iter()
+                        .map(|name| format!("{parent}/{name}"))
+                        .collect()
+                } else {
+                    Vec::new()
+                };
+                self.ids.push(entry.key().clone());
+                entry.insert(Workspace {
+                    uri,
+                    decoded_uri,
+                    decoded_folder_names,
+                });
+            }
+        }
+    }
+
+    /// Get the given workspace `ids` along with their decoded URIs, for matching.
+    ///
+    /// Skip unknown IDs.  Return every ID once more for each decoded folder name
+    /// of its workspace, see [`Workspace::decoded_folder_names`], right after
+    /// its decoded URI; [`find_matching_uris`] returns every ID only once.
+    fn decoded<'a, I>(&'a self, ids: I) -> impl Iterator<Item = (&'a str, &'a str)>
+    where
+        I: IntoIterator<Item = &'a String>,
+    {
+        ids.into_iter()
+            .filter_map(|id| self.by_id.get_key_value(id))
+            .flat_map(|(id, workspace)| {
+                std::iter::once(&workspace.decoded_uri)
+                    .chain(&workspace.decoded_folder_names)
+                    .map(move |decoded| (id.as_str(), decoded.as_str()))
+            })
+    }
+
+    /// Get the URI of the workspace with the given `id`.
+    ///
+    /// Fall back to `id` itself for unknown IDs, since IDs are URIs too.
+    fn uri<'a>(&'a self, id: &'a str) -> &'a str {
+        self.by_id
+            .get(id)
+            .map_or(id, |workspace| workspace.uri.as_str())
+    }
+}
+
+/// Identify the state of a file, to tell whether it changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct FileStamp {
+    /// When the file was last modified.
+    modified: SystemTime,
+    /// The device the file resides on.
+    device: u64,
+    /// The inode of the file.
+    inode: u64,
+}
+
+impl FileStamp {
+    /// Get the stamp of the file at `path`, if any.
+    ///
+    /// Besides the modification time, include the device and inode of the file,
+    /// to tell when the file was replaced, e.g. by an atomic rename, even if its
+    /// modification time did not advance.
+    fn of_path(path: &Path) -> Option<Self> {
+        let metadata = std::fs::metadata(path).ok()?;
+        Some(Self {
+            modified: metadata.modified().ok()?,
+            device: metadata.dev(),
+            inode: metadata.ino(),
+        })
+    }
+}
+
+/// Get the stamps of all files at `paths`, see [`FileStamp::of_path`].
+fn file_stamps(paths: &[PathBuf]) -> Vec<Option<FileStamp>> {
+    paths.iter().map(|path| FileStamp::of_path(path)).collect()
+}
+
+/// Workspaces of a variant in the persistent cache.
+///
+/// With [`ProviderConfig::cache`] we store the workspaces of each variant in
+/// a JSON file under `$XDG_STATE_HOME`, so that a new process of this service
+/// can serve workspaces without loading them if the databases did not change.
+/// The cache only holds workspaces from databases; workspaces commands run
+/// anew every time, see [`SearchProvider::spawn_workspaces_command`].
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedWorkspaces<'a> {
+    /// The stamps of all databases the workspaces were loaded from.
+    database_stamps: Cow<'a, [Option<FileStamp>]>,
+    /// The URIs of all workspaces, in order.
+    uris: Cow<'a, [String]>,
+}
+
+impl CachedWorkspaces<'_> {
+    /// The path of the cache file for the variant with `app_id`.
+    fn path(app_id: &str) -> PathBuf {
+        xdg::state_home()
+            .join("gnome-search-providers-vscode")
+            .join(format!("{app_id}.json"))
+    }
+
+    /// Read the cached workspace URIs of the variant with `app_id`.
+    ///
+    /// Return `None` if there is no cache, if it fails to read, or if it was
+    /// written for databases with other stamps than `database_stamps`.
+    fn read(app_id: &str, database_stamps: &[Option<FileStamp>]) -> Option<Vec<String>> {
+        let path = Self::path(app_id);
+        let contents = std::fs::read(&path).ok()?;
+        match serde_json::from_slice::<CachedWorkspaces>(&contents) {
+            Ok(cached) if cached.database_stamps.as_ref() == database_stamps => {
+                Some(cached.uris.into_owned())
+            }
+            Ok(_) => {
+                glib::debug!("Cached workspaces at {} are outdated", path.display());
+                None
+            }
+            Err(error) => {
+                glib::warn!(
+                    "Ignoring invalid cached workspaces at {}: {error}",
+                    path.display()
+                );
+                None
+            }
+        }
+    }
+
+    /// Cache workspace `uris` of the variant with `app_id`, loaded from databases
+    /// with `database_stamps`.
+    ///
+    /// Write to a temporary file first and rename it over the cache, so that
+    /// we never leave a partially written cache behind.
+    fn write(app_id: &str, database_stamps: &[Option<FileStamp>], uris: &[String]) {
+        let path = Self::path(app_id);
+        let cached = CachedWorkspaces {
+            database_stamps: Cow::Borrowed(database_stamps),
+            uris: Cow::Borrowed(uris),
+        };
+        let temp_path = path.with_extension("json.tmp");
+        let result = serde_json::to_vec(&cached)
+            .map_err(std::io::Error::from)
+            .and_then(|contents| {
+                std::fs::create_dir_all(path.parent().unwrap())?;
+                std::fs::write(&temp_path, contents)?;
+                std::fs::rename(&temp_path, &path)
+            });
+        if let Err(error) = result {
+            glib::warn!(
+                "Failed to cache workspaces of {app_id} at {}: {error}",
+                path.display()
+            );
+        }
+    }
+}
+
+/// Emit `WorkspacesReloaded` with `count` workspaces of the variant at
+/// `object_path` on `connection`.
+fn emit_workspaces_reloaded(
+    connection: &gio::DBusConnection,
+    object_path: &str,
+    count: u32,
+) -> Result<(), glib::Error> {
+    connection.emit_signal(
+        None,
+        object_path,
+        "de.swsnr.VSCodeSearchProvider.Variant",
+        "WorkspacesReloaded",
+        Some(&(count,).to_variant()),
+    )
+}
+
+/// The maximum number of search terms we consider.
+///
+/// Every term gets matched against every workspace, so a client which sends
+/// hundreds of terms would make searches needlessly slow.  Nobody
+/// reasonably types more terms than this.
+const MAX_SEARCH_TERMS: usize = 16;
+
+/// Limit `terms` to the first [`MAX_SEARCH_TERMS`] terms.
+///
+/// Warn if we drop any terms.
+fn limit_search_terms(mut terms: Vec<String>) -> Vec<String> {
+    if MAX_SEARCH_TERMS < terms.len() {
+        glib::warn!(
+            "Ignoring all but the first {MAX_SEARCH_TERMS} of {} search terms",
+            terms.len()
+        );
+        terms.truncate(MAX_SEARCH_TERMS);
+    }
+    terms
+}
+
+/// Log the outcome of a search for `terms` in the workspaces of `app_id`.
+///
+/// Besides the message, record the number of matched and searched workspaces
+/// in the structured fields `N_MATCHED` and `N_TOTAL`, and the terms in
+/// `SEARCH_TERMS`, for log aggregation.
+fn log_search(app_id: &str, terms: &[String], n_matched: usize, n_total: usize) {
+    glib::log_structured!(
+        G_LOG_DOMAIN,
+        glib::LogLevel::Debug,
+        {
+            "MESSAGE" => "{} of {} workspaces of {} match {:?}", n_matched, n_total, app_id, terms;
+            "N_MATCHED" => "{}", n_matched;
+            "N_TOTAL" => "{}", n_total;
+            "SEARCH_TERMS" => "{:?}", terms;
+        }
+    );
+}
+
+struct SearchProvider {
+    /// This provider itself, for callbacks which outlive calls.
+    this: Weak<SearchProvider>,
+    app: Application,
+    variant: CodeVariant,
+    code_app_info: DesktopAppInfo,
+    /// The object path this provider is registered at.
+    object_path: String,
+    workspaces: RefCell<Workspaces>,
+    /// Why workspaces failed to load, if they never loaded successfully.
+    ///
+    /// See [`ProviderConfig::error_result`].
+    load_error: RefCell<Option<String>>,
+    config: ProviderConfig,
+    /// The command the last launch would have run, in dry-run mode.
+    last_dry_run_command: RefCell<Vec<String>>,
+    /// The terms of the last initial or subsearch.
+    last_search_terms: RefCell<Vec<String>>,
+    /// The serialized icon of the app, resolved on first use.
+    ///
+    /// See [`SearchProvider::app_icon`].
+    app_icon: OnceCell<Option<Variant>>,
+}
+
+impl SearchProvider {
+    fn new(
+        app: Application,
+        variant: CodeVariant,
+        code_app: DesktopAppInfo,
+        object_path: String,
+        workspaces: Workspaces,
+        load_error: Option<String>,
+        config: ProviderConfig,
+    ) -> Rc<Self> {
+        Rc::new_cyclic(|this| Self {
+            this: this.clone(),
+            app,
+            variant,
+            code_app_info: code_app,
+            object_path,
+            workspaces: RefCell::new(workspaces),
+            load_error: RefCell::new(load_error),
+            config,
+            last_dry_run_command: RefCell::default(),
+            last_search_terms: RefCell::default(),
+            app_icon: OnceCell::new(),
+        })
+    }
+
+    /// Reload workspaces if the database changed since we last loaded them.
+    ///
+    /// Emit `WorkspacesReloaded` after reloading workspaces.  Keep the current
+    /// workspaces if reloading fails, and update the load error if workspaces
+    /// never loaded successfully.
+    ///
+    /// This never awaits anything, so calls of different clients can't
+    /// interleave on the main context: a later call always sees the stamps the
+    /// earlier call recorded, and doesn't reload again.  Add workspaces from the
+    /// workspaces command in the background, see [`Self::spawn_workspaces_command`].
+    fn refresh_workspaces(&self) {
+        let db_paths = self.variant.database_paths();
+        let db_path = &db_paths[0];
+        let stamps = file_stamps(&db_paths);
+        if stamps[0].is_none() || stamps == self.workspaces.borrow().database_stamps {
+            return;
+        }
+        glib::info!(
+            "Database {} changed, reloading workspaces of {}",
+            db_path.display(),
+            self.app_id()
+        );
+        match load_cached_workspaces_of_variant(
+            &self.variant,
+            &db_paths,
+            &stamps,
+            self.config.cache,
+        ) {
+            Ok(uris) => {
+                let workspaces = Workspaces::new(uris, stamps, self.config.workspace_folder_names);
+                let count = u32::try_from(workspaces.ids.len()).unwrap_or(u32::MAX);
+                *self.workspaces.borrow_mut() = workspaces;
+                *self.load_error.borrow_mut() = None;
+                self.emit_workspaces_reloaded(count);
+                self.spawn_workspaces_command();
+            }
+            Err(error) => {
+                glib::warn!(
+                    "Failed to reload workspaces from {}, keeping current workspaces: {error}",
+                    db_path.display()
+                );
+                if let Some(load_error) = self.load_error.borrow_mut().as_mut() {
+                    *load_error = error.to_string();
+                }
+            }
+        }
+    }
+
+    /// Add workspaces from the workspaces command of our variant, if any.
+    ///
+    /// Run the command in the background on the main context, see
+    /// [`load_workspaces_from_command`], and add its workspaces after all
+    /// workspaces from databases once it finishes, see [`Self::add_command_workspaces`].
+    /// Keep only the workspaces from databases if the command fails.
+    ///
+    /// Return a handle to await the command, if any.
+    fn spawn_workspaces_command(&self) -> Option<glib::JoinHandle<()>> {
+        let command = self.variant.workspaces_command()?;
+        let this = self.this.clone();
+        let stamps = self.workspaces.borrow().database_stamps.clone();
+        // Keep the service alive while the command runs.
+        let guard = self.app.hold();
+        Some(glib::spawn_future_local(async move {
+            let result = load_workspaces_from_command(&command, WORKSPACES_COMMAND_TIMEOUT).await;
+            drop(guard);
+            let Some(this) = this.upgrade() else {
+                return;
+            };
+            match result {
+                Ok(uris) => {
+                    this.add_command_workspaces(&stamps, uris);
+                }
+                Err(error) => glib::warn!(
+                    "Skipping workspaces command {}, failed to load workspaces: {error}",
+                    command.display()
+                ),
+            }
+        }))
+    }
+
+    /// Add `uris` from the workspaces command to the workspaces loaded from
+    /// databases with `stamps`.
+    ///
+    /// Discard `uris` if workspaces reloaded while the command ran, because the
+    /// reload runs the command anew.  Emit `WorkspacesReloaded` after adding
+    /// workspaces.  Return whether we added `uris`.
+    fn add_command_workspaces(&self, stamps: &[Option<FileStamp>], uris: Vec<String>) -> bool {
+        let count = {
+            let mut workspaces = self.workspaces.borrow_mut();
+            if workspaces.database_stamps != stamps {
+                glib::debug!(
+                    "Workspaces of {} reloaded while the workspaces command ran, discarding its workspaces",
+                    self.app_id()
+                );
+                return false;
+            }
+            workspaces.extend(uris, self.config.workspace_folder_names);
+            workspaces.ids.len()
+        };
+        self.emit_workspaces_reloaded(u32::try_from(count).unwrap_or(u32::MAX));
+        true
+    }
+
+    /// Emit the `WorkspacesReloaded` signal with the new `count` of workspaces.
+    fn emit_workspaces_reloaded(&self, count: u32) {
+        if let Some(connection) = self.app.dbus_connection() {
+            if let Err(error) = emit_workspaces_reloaded(&connection, &self.object_path, count) {
+                glib::warn!("Failed to emit WorkspacesReloaded: {error}");
+            }
+        }
+    }
+
+    /// Launch the given `uri`, if any, or launch the app directly.
+    ///
+    /// Launch the uri with this code via `gio launch` wrapped in `systemd-run`,
+    /// to make damn sure that Visual Studio Code gets its own scope.  Variants
+    /// can use a different command than `gio launch` with their launch template.
+    ///
+    /// We cannot launch the desktop app file directly, e.g. with `launch_uris`,
+    /// and the move the new process to a separate scope using sytemd's D-Bus
+    /// API
+    /// because vscode aggressively forks into background so fast, that we will
+    /// have lost track of its forked children before we get a chance to move
+    /// the whole process tree to a new scope.  This effectively means that the
+    /// actual Visual Studio Code process which shows the window then remains a child of our
+    /// own service scope, and lives and dies with the process of this search
+    /// provider service.  And since we auto-quit our service after a few idle
+    /// minutes we'd take down open Visual Studio Code windows with us.
+    ///
+    /// Since we can't get this down race-free via Gio/GLib itself, spawn a new
+    /// scope first with systemd-run and then spawn the app in it, per the
+    /// launch template of the variant, see [`CodeVariant::configured_launch_template`].
+    ///
+    /// Name the scope after the app, following the systemd naming convention for
+    /// application scopes, so that [`Self::is_running`] finds it, see
+    /// [`scope_unit_name`].  If launching fails because the scope exists
+    /// already, retry once with a new scope.
+    ///
+    /// With [`LaunchConfig::no_scope`] run the launch template directly, and
+    /// accept all the issues described above.
+    ///
+    /// With [`LaunchConfig::dbus_activation`] activate apps which support D-Bus
+    /// activation over D-Bus instead, see [`Self::activate_over_dbus`].
+    async fn launch_uri(&self, uri: Option<&str>) -> Result<(), glib::Error> {
+        if !self.is_launchable() {
+            return Err(glib::Error::new(
+                IOErrorEnum::NotFound,
+                &format!("{} is no longer installed", self.app_id()),
+            ));
+        }
+        if uses_dbus_activation(self.config.launch, &self.code_app_info) {
+            return self.activate_over_dbus(uri).await;
+        }
+        let app_desktop_file = self.code_app_info.filename().unwrap();
+        let template = self.variant.configured_launch_template();
+        let app_command = if template.contains(&LaunchArg::Command) {
+            desktop_entry_command(&self.code_app_info)?
+        } else {
+            Vec::new()
+        };
+        let options = self.variant.launch_options();
+        let launch_command = expand_launch_template(
+            template,
+            &LaunchTemplateArgs {
+                desktop_file: &app_desktop_file,
+                command: &app_command,
+                options: &options,
+                uri,
+            },
+        );
+        if self.config.launch.no_scope {
+            return self
+                .run_launch_command(launch_command, "without a new scope")
+                .await;
+        }
+        let scope_name = scope_unit_name(self.app_id());
+        match self.launch_in_scope(&scope_name, &launch_command).await {
+            // systemd-run fails if the unit exists already; our scope names make
+            // this practically impossible, but try once more just in case.
+            Err(error) if self.has_unit(&scope_name) => {
+                glib::warn!(
+                    "Scope {scope_name} exists already, retrying with another scope: {error}"
+                );
+                let scope_name = scope_unit_name(self.app_id());
+                self.launch_in_scope(&scope_name, &launch_command).await
+            }
+            result => result,
+        }
+    }
+
+    /// Run `launch_command` in a new scope with the given `scope_name`.
+    ///
+    /// systemd-run only executes the command once systemd registered the scope
+    /// and moved systemd-run into it, so the app always starts in its own scope,
+    /// without any delay on our side.
+    async fn launch_in_scope(
+        &self,
+        scope_name: &str,
+        launch_command: &[&OsStr],
+    ) -> Result<(), glib::Error> {
+        let unit_arg = format!("--unit={scope_name}");
+        let mut command = vec![
+            OsStr::new("/usr/bin/systemd-run"),
+            OsStr::new("--user"),
+            OsStr::new("--scope"),
+            OsStr::new(&unit_arg),
+            OsStr::new("--same-dir"),
+        ];
+        command.extend(launch_command);
+        self.run_launch_command(command, &format!("in scope {scope_name}"))
+            .await
+    }
+
+    /// Run the given launch `command`, or record it in dry-run mode.
+    ///
+    /// `scope_description` tells where the command runs, for logging.  Fail if
+    /// `command` fails.
+    async fn run_launch_command(
+        &self,
+        command: Vec<&OsStr>,
+        scope_description: &str,
+    ) -> Result<(), glib::Error> {
+        // The command line contains the URI which may include sensitive details
+        // of remote hosts, so only log it at debug level.
+        if self.config.launch.dry_run {
+            glib::info!(
+                "Dry run, not launching {} {scope_description}",
+                self.app_id()
+            );
+            glib::debug!("Not running command {command:?}");
+            *self.last_dry_run_command.borrow_mut() = command
+                .iter()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect();
+            return Ok(());
+        }
+        glib::info!("Launching {} {scope_description}", self.app_id());
+        glib::debug!("Running command {command:?}");
+        let process = gio::Subprocess::newv(command.as_slice(), gio::SubprocessFlags::NONE)?;
+        process.wait_future().await?;
+        if !process.is_successful() {
+            return Err(glib::Error::new(
+                IOErrorEnum::Failed,
+                &format!(
+                    "Failed to launch {} {scope_description}, command exited with status {}",
+                    self.app_id(),
+                    process.exit_status()
+                ),
+            ));
+        }
+        glib::info!("Launched {} {scope_description}", self.app_id());
+        Ok(())
+    }
+
+    /// Whether systemd has a unit with the given `name` loaded.
+    ///
+    /// Assume it has none if we fail to tell.
+    fn has_unit(&self, name: &str) -> bool {
+        let Some(connection) = self.app.dbus_connection() else {
+            return false;
+        };
+        has_units_matching(&connection, &[], vec![name.to_owned()]).unwrap_or_else(|error| {
+            glib::warn!("Failed to check whether unit {name} exists: {error}");
+            false
+        })
+    }
+
+    /// Activate the app of this provider over D-Bus, and open `uri` if any.
+    ///
+    /// Call `Open` or `Activate` of the `org.freedesktop.Application` interface
+    /// of the app, per the desktop entry specification.  The bus then starts the
+    /// app in a scope of its own if it does not run yet, so we need no scope.
+    ///
+    /// In dry-run mode record an equivalent `gdbus` command line instead.
+    async fn activate_over_dbus(&self, uri: Option<&str>) -> Result<(), glib::Error> {
+        let connection = self.app.dbus_connection().ok_or_else(|| {
+            glib::Error::new(IOErrorEnum::NotConnected, "Not connected to the bus")
+        })?;
+        let app_id = self.code_app_info.id().map_or_else(
+            || self.app_id().to_owned(),
+            |id| id.trim_end_matches(".desktop").to_owned(),
+        );
+        let object_path = application_object_path(&app_id);
+        let (method, parameters) = application_activation_call(uri);
+        if self.config.launch.dry_run {
+            glib::info!("Dry run, not activating {app_id} over D-Bus");
+            *self.last_dry_run_command.borrow_mut() = vec![
+                "gdbus".to_owned(),
+                "call".to_owned(),
+                "--session".to_owned(),
+                "--dest".to_owned(),
+                app_id,
+                "--object-path".to_owned(),
+                object_path,
+                "--method".to_owned(),
+                format!("org.freedesktop.Application.{method}"),
+                parameters.print(true).to_string(),
+            ];
+            return Ok(());
+        }
+        glib::info!("Activating {app_id} over D-Bus");
+        connection
+            .call_future(
+                Some(&app_id),
+                &object_path,
+                "org.freedesktop.Application",
+                method,
+                Some(&parameters),
+                None,
+                gio::DBusCallFlags::NONE,
+                -1,
+            )
+            .await?;
+        glib::info!("Activated {app_id} over D-Bus");
+        Ok(())
+    }
+
+    /// Whether the app of this provider is still launchable.
+    ///
+    /// Gio already ignores desktop entries whose `TryExec` program does not exist
+    /// when loading them; check again before launching, in case the app was
+    /// removed since we loaded its desktop entry.
+    fn is_launchable(&self) -> bool {
+        self.code_app_info
+            .string("TryExec")
+            .map_or(true, |try_exec| {
+                try_exec.is_empty() || glib::find_program_in_path(try_exec).is_some()
+            })
+    }
+
+    /// Add the result to launch the app to `results` if enabled and there are no `results`.
+    ///
+    /// See [`DisplayConfig::launch_result`].  If workspaces failed to load return
+    /// only the result which tells so instead, see [`ProviderConfig::error_result`].
+    fn with_launch_result<'a>(&self, mut results: Vec<&'a str>) -> Vec<&'a str> {
+        if self.load_error.borrow().is_some() {
+            return vec![LOAD_ERROR_RESULT_ID];
+        }
+        if self.config.display.launch_result && results.is_empty() {
+            results.push(LAUNCH_APP_RESULT_ID);
+        }
+        results
+    }
+
+    /// Get the metadata of all results with the given `identifiers`.
+    fn result_metas(&self, identifiers: &[String]) -> Vec<VariantDict> {
+        let workspaces = self.workspaces.borrow();
+        let app_icon = self.app_icon();
+        let uris = identifiers
+            .iter()
+            .filter(|id| is_workspace_result_id(id))
+            .map(|id| workspaces.uri(id))
+            .collect::<Vec<_>>();
+        let mut workspace_names_and_descriptions =
+            names_and_descriptions_of_uris(self.config.display, &uris).into_iter();
+        identifiers
+            .iter()
+            .map(|id| {
+                let metas = VariantDict::new(None);
+                metas.insert("id", id.as_str());
+                if self.config.display.clipboard_text && is_workspace_result_id(id) {
+                    let text = clipboard_text_of_uri(workspaces.uri(id));
+                    metas.insert("clipboardText", text.as_str());
+                }
+                let (name, description) = if id == LAUNCH_APP_RESULT_ID {
+                    self.launch_result_name_and_description()
+                } else if id == LOAD_ERROR_RESULT_ID {
+                    self.load_error_name_and_description()
+                } else {
+                    let (name, description) =
+                        workspace_names_and_descriptions.next().unwrap_or_default();
+                    let uri = workspaces.uri(id);
+                    let name = match self.config.display.max_name_length {
+                        Some(max_length) => truncate_end(&name, max_length),
+                        None => name,
+                    };
+                    (
+                        name,
+                        self.config.display.description.describe(uri, description),
+                    )
+                };
+                metas.insert("name", name.as_str());
+                metas.insert("description", description.as_str());
+                if let Some(app_icon) = &app_icon {
+                    metas.insert("icon", app_icon);
+                }
+                metas
+            })
+            .collect()
+    }
+
+    /// Get the name and description of the result to launch the app.
+    fn launch_result_name_and_description(&self) -> (String, String) {
+        let name = self.code_app_info.name().to_string();
+        let description = format!("Open {name} without any workspace");
+        (name, description)
+    }
+
+    /// Get the name and description of the result which tells that workspaces failed to load.
+    fn load_error_name_and_description(&self) -> (String, String) {
+        let name = format!(
+            "Recent workspaces of {} unavailable",
+            self.code_app_info.name()
+        );
+        let description = self.load_error.borrow().clone().unwrap_or_default();
+        (name, description)
+    }
+
+    /// Get the serialized icon of the app this provider launches.
+    ///
+    /// Ignore empty `Icon=` keys in desktop entries, for which Gio still returns
+    /// a themed icon without any meaningful name, as well as themed icons which
+    /// do not resolve, see [`is_resolvable_icon`].  Without a usable icon in the
+    /// desktop entry fall back to the fallback icon of the variant, and then to
+    /// the generic `text-editor` icon.
+    ///
+    /// Resolve the icon only once, because resolving looks at icon themes on
+    /// disk, and GNOME Shell asks for result metas on every keystroke.
+    fn app_icon(&self) -> Option<Variant> {
+        self.app_icon
+            .get_or_init(|| self.resolve_app_icon())
+            .clone()
+    }
+
+    /// Resolve the serialized icon of the app, see [`SearchProvider::app_icon`].
+    fn resolve_app_icon(&self) -> Option<Variant> {
+        let has_icon = self
+            .code_app_info
+            .string("Icon")
+            .is_some_and(|icon| !icon.trim().is_empty());
+        let icon = if has_icon {
+            self.code_app_info.icon().filter(is_resolvable_icon)
+        } else {
+            None
+        };
+        icon.map_or_else(
+            || {
+                gio::ThemedIcon::from_names(&[self.variant.fallback_icon, "text-editor"])
+                    .serialize()
+            },
+            |icon| icon.serialize(),
+        )
+    }
+
+    /// The app ID of the app this provider launches, i.e. its desktop ID without `.desktop`.
+    fn app_id(&self) -> &str {
+        self.variant.app_id()
+    }
+
+    /// Whether an instance of the app of this provider currently runs.
+    ///
+    /// Ask systemd for any active application scope of the app, see
+    /// [`application_scope_patterns`].
+    fn is_running(&self, connection: &gio::DBusConnection) -> Result<bool, glib::Error> {
+        has_units_matching(
+            connection,
+            &["active"],
+            application_scope_patterns(self.app_id()),
+        )
+    }
+
+    /// The number of workspaces we currently serve.
+    ///
+    /// Do not reload workspaces for this: getting a property should have no
+    /// side effects, and clients can await `WorkspacesReloaded` instead.
+    fn workspace_count(&self) -> u32 {
+        u32::try_from(self.workspaces.borrow().ids.len()).unwrap_or(u32::MAX)
+    }
+
+    /// Get the value of the property `name` of our variant interface.
+    fn get_property(&self, connection: &gio::DBusConnection, name: &str) -> Variant {
+        match name {
+            "Running" => self
+                .is_running(connection)
+                .unwrap_or_else(|error| {
+                    glib::warn!("Failed to check whether {} runs: {error}", self.app_id());
+                    false
+                })
+                .to_variant(),
+            "LastDryRunCommand" => self.last_dry_run_command.borrow().to_variant(),
+            "LastSearchTerms" => self.last_search_terms.borrow().to_variant(),
+            "WorkspaceCount" => self.workspace_count().to_variant(),
+            _ => unreachable!("Unknown property {name}"),
+        }
+    }
+
+    /// Handle the given search provider method `call`.
+    ///
+    /// Perform any side effects triggered by the call and return the appropriate
+    /// result.
+    async fn handle_call(
+        &self,
+        call: SearchProvider2Method,
+    ) -> Result<Option<Variant>, glib::Error> {
+        // Hold on to the application while we're processing a DBus call.  The
+        // guard lives until this future completes, so the inactivity timeout of
+        // the application never fires while a launch is still in progress.
+        let _guard = self.app.hold();
+        match call {
+            SearchProvider2Method::GetInitialResultSet(GetInitialResultSet(terms)) => {
+                let terms = limit_search_terms(terms);
+                glib::debug!(
+                    "Searching for terms {terms:?} in workspaces of {} from database {}",
+                    self.app_id(),
+                    self.variant.database_path().display()
+                );
+                self.refresh_workspaces();
+                let workspaces = self.workspaces.borrow();
+                let decoded = workspaces.decoded(&workspaces.ids).collect::<Vec<_>>();
+                let matched =
+                    find_matching_uris_tiered(self.config.scoring, &decoded, terms.as_slice());
+                log_search(self.app_id(), &terms, matched.len(), workspaces.ids.len());
+                let results = self.with_launch_result(matched);
+                *self.last_search_terms.borrow_mut() = terms;
+                Ok(Some(results.to_variant()))
+            }
+            SearchProvider2Method::GetSubsearchResultSet(GetSubsearchResultSet(
+                previous_results,
+                terms,
+            )) => {
+                let terms = limit_search_terms(terms);
+                glib::debug!(
+                    "Searching for terms {terms:?} in {} previous results",
+                    previous_results.len()
+                );
+                // Subsearches only narrow down previous results, so all of them
+                // are among our workspaces, and already decoded.
+                let workspaces = self.workspaces.borrow();
+                let matched = find_matching_uris(
+                    self.config.scoring,
+                    workspaces.decoded(&previous_results),
+                    terms.as_slice(),
+                );
+                log_search(self.app_id(), &terms, matched.len(), previous_results.len());
+                let results = self.with_launch_result(matched);
+                *self.last_search_terms.borrow_mut() = terms;
+                Ok(Some(results.to_variant()))
+            }
+            SearchProvider2Method::GetResultMetas(GetResultMetas(identifiers))
+                if identifiers.is_empty() =>
+            {
+                // Some shells ask for metadata of no results at all; don't bother
+                // to look up the app icon for these.
+                Ok(Some(Vec::<VariantDict>::new().into()))
+            }
+            SearchProvider2Method::GetResultMetas(GetResultMetas(identifiers)) => {
+                glib::debug!("Get metadata for {identifiers:?}");
+                Ok(Some(self.result_metas(&identifiers).into()))
+            }
+            SearchProvider2Method::ActivateResult(ActivateResult(identifier, _, _))
+                if identifier == LAUNCH_APP_RESULT_ID =>
+            {
+                glib::info!("Launching application {} directly", self.app_id());
+                self.launch_uri(None).await?;
+                Ok(None)
+            }
+            SearchProvider2Method::ActivateResult(ActivateResult(identifier, _, _))
+                if identifier == LOAD_ERROR_RESULT_ID =>
+            {
+                glib::debug!("Not activating load error result of {}", self.app_id());
+                Ok(None)
+            }
+            SearchProvider2Method::ActivateResult(ActivateResult(identifier, _, _)) => {
+                let uri = self.workspaces.borrow().uri(&identifier).to_owned();
+                glib::debug!("Launching application {} with URI {uri}", self.app_id());
+                self.launch_uri(Some(&uri)).await?;
+                Ok(None)
+            }
+            SearchProvider2Method::LaunchSearch(_) => {
+                glib::info!("Launching application {} directly", self.app_id());
+                self.launch_uri(None).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Handle the given variant method `call`.
+    fn handle_variant_call(&self, call: VariantMethod) -> Result<Option<Variant>, glib::Error> {
+        // Hold on to the application while we're processing a DBus call.
+        let _guard = self.app.hold();
+        match call {
+            VariantMethod::ForgetWorkspace(ForgetWorkspace(uri)) => {
+                self.forget_workspace(&uri)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Remove the workspace `uri` from the recent workspaces of Visual Studio Code.
+    ///
+    /// Refuse to modify the database while the app runs, because it would
+    /// overwrite our changes, see [`Self::is_running`].  Remove the workspace
+    /// from all databases, and reload workspaces afterwards.
+    #[cfg(feature = "forget-workspace")]
+    fn forget_workspace(&self, uri: &str) -> Result<(), glib::Error> {
+        let connection = self.app.dbus_connection().ok_or_else(|| {
+            glib::Error::new(IOErrorEnum::NotConnected, "Not connected to the bus")
+        })?;
+        if self.is_running(&connection)? {
+            return Err(glib::Error::new(
+                IOErrorEnum::Busy,
+                &format!("Refusing to forget {uri} while {} runs", self.app_id()),
+            ));
+        }
+        let mut forgotten = false;
+        for db_path in self.variant.database_paths() {
+            if forget_workspace_in_database(&db_path, &self.variant.storage_key(), uri)? {
+                glib::info!("Forgot workspace in {}", db_path.display());
+                forgotten = true;
+            }
+        }
+        self.refresh_workspaces();
+        if forgotten {
+            Ok(())
+        } else {
+            Err(glib::Error::new(
+                IOErrorEnum::NotFound,
+                &format!("No recent workspace {uri}"),
+            ))
+        }
+    }
+
+    /// Remove the workspace `uri` from the recent workspaces of Visual Studio Code.
+    ///
+    /// Always fail, since we're built without the `forget-workspace` feature.
+    #[cfg(not(feature = "forget-workspace"))]
+    #[allow(clippy::unused_self, reason = "Same signature as with the feature")]
+    fn forget_workspace(&self, uri: &str) -> Result<(), glib::Error> {
+        Err(glib::Error::new(
+            IOErrorEnum::NotSupported,
+            &format!("Cannot forget {uri}, built without the forget-workspace feature"),
+        ))
+    }
+
+    /// Register this search provider under `object_path` on a D-Bus `connection`.
+    ///
+    /// Register the search provider interface as well as our variant interface.
+    fn register(
+        self: &Rc<Self>,
+        connection: &gio::DBusConnection,
+        object_path: &str,
+        interface_info: &DBusInterfaceInfo,
+        variant_interface_info: &DBusInterfaceInfo,
+    ) -> Result<Vec<gio::RegistrationId>, glib::Error> {
+        let search_provider = self.clone();
+        let variant_provider = self.clone();
+        let provider = self.clone();
+        let search_provider_id = connection
+            .register_object(object_path, interface_info)
+            .typed_method_call::<SearchProvider2Method>()
+            .invoke_and_return_future_local(move |_, _, call| {
+                let search_provider = provider.clone();
+                async move { search_provider.handle_call(call).await }
+            })
+            .build()?;
+        let variant_id = connection
+            .register_object(object_path, variant_interface_info)
+            .typed_method_call::<VariantMethod>()
+            .invoke_and_return_future_local(move |_, _, call| {
+                let provider = variant_provider.clone();
+                async move { provider.handle_variant_call(call) }
+            })
+            .property(move |connection, _, _, _, name| {
+                search_provider.get_property(&connection, name)
+            })
+            .build();
+        match variant_id {
+            Ok(variant_id) => Ok(vec![search_provider_id, variant_id]),
+            Err(error) => {
+                // Don't leave a half-registered object behind
+                if let Err(error) = connection.unregister_object(search_provider_id) {
+                    glib::warn!("Failed to unregister {object_path}: {error}");
+                }
+                Err(error)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Variant)]
+pub struct ForgetWorkspace(String);
+
+/// Method calls our variant interface supports.
+#[derive(Debug)]
+pub enum VariantMethod {
+    ForgetWorkspace(ForgetWorkspace),
+}
+
+impl DBusMethodCall for VariantMethod {
+    fn parse_call(
+        _obj_path: &str,
+        _interface: Option<&str>,
+        method: &str,
+        params: glib::Variant,
+    ) -> Result<Self, glib::Error> {
+        match method {
+            "ForgetWorkspace" => params
+                .get::<ForgetWorkspace>()
+                .map(VariantMethod::ForgetWorkspace)
+                .ok_or_else(invalid_parameters),
+            _ => Err(glib::Error::new(
+                IOErrorEnum::InvalidArgument,
+                "Unexpected method",
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Variant)]
+pub struct OpenInVariant(String, String);
+
+#[derive(Debug, Variant)]
+pub struct OpenContainingFolder(String);
+
+#[derive(Debug, Variant)]
+pub struct RescanVariants;
+
+#[derive(Debug, Variant)]
+pub struct GetInfo;
+
+#[derive(Debug, Variant)]
+pub struct ListProviders;
+
+/// Method calls our manager interface supports.
+#[derive(Debug)]
+pub enum ManagerMethod {
+    OpenInVariant(OpenInVariant),
+    OpenContainingFolder(OpenContainingFolder),
+    RescanVariants(RescanVariants),
+    GetInfo(GetInfo),
+    ListProviders(ListProviders),
+}
+
+impl DBusMethodCall for ManagerMethod {
+    fn parse_call(
+        _obj_path: &str,
+        _interface: Option<&str>,
+        method: &str,
+        params: glib::Variant,
+    ) -> Result<Self, glib::Error> {
+        match method {
+            "OpenInVariant" => params
+                .get::<OpenInVariant>()
+                .map(ManagerMethod::OpenInVariant)
+                .ok_or_else(invalid_parameters),
+            "OpenContainingFolder" => params
+                .get::<OpenContainingFolder>()
+                .map(ManagerMethod::OpenContainingFolder)
+                .ok_or_else(invalid_parameters),
+            "RescanVariants" => params
+                .get::<RescanVariants>()
+                .map(ManagerMethod::RescanVariants)
+                .ok_or_else(invalid_parameters),
+            "GetInfo" => params
+                .get::<GetInfo>()
+                .map(ManagerMethod::GetInfo)
+                .ok_or_else(invalid_parameters),
+            "ListProviders" => params
+                .get::<ListProviders>()
+                .map(ManagerMethod::ListProviders)
+                .ok_or_else(invalid_parameters),
+            _ => Err(glib::Error::new(
+                IOErrorEnum::InvalidArgument,
+                "Unexpected method",
+            )),
+        }
+    }
+}
+
+/// Get the URI of the directory containing the local workspace `uri`.
+///
+/// Fail if `uri` does not denote a local file, or has no parent.
+fn containing_folder(uri: &str) -> Result<String, glib::Error> {
+    let parsed_uri = glib::Uri::parse(uri, UriFlags::NONE)?;
+    if parsed_uri.scheme() != "file" || parsed_uri.host().is_some_and(|host| !host.is_empty()) {
+        return Err(glib::Error::new(
+            IOErrorEnum::NotSupported,
+            &format!("{uri} is not a local workspace"),
+        ));
+    }
+    gio::File::for_uri(uri)
+        .parent()
+        .map(|parent| parent.uri().into())
+        .ok_or_else(|| {
+            glib::Error::new(
+                IOErrorEnum::NotFound,
+                &format!("{uri} has no containing folder"),
+            )
+        })
+}
+
+/// The git commit this service was built from, if known.
+///
+/// Taken from `$VSCODE_SEARCH_PROVIDER_GIT_COMMIT` at build time, which the
+/// `Makefile` sets.
+const GIT_COMMIT: Option<&str> = option_env!("VSCODE_SEARCH_PROVIDER_GIT_COMMIT");
+
+/// The D-Bus interfaces of a search provider.
+#[derive(Debug, Clone)]
+struct ProviderInterfaces {
+    /// The GNOME Shell search provider interface.
+    search_provider: DBusInterfaceInfo,
+    /// Our own interface for variants.
+    variant: DBusInterfaceInfo,
+}
+
+/// A search provider registered on the bus.
+struct RegisteredProvider {
+    provider: Rc<SearchProvider>,
+    registration_ids: Vec<gio::RegistrationId>,
+}
+
+/// Manage all search providers of this service.
+struct Manager {
+    app: Application,
+    interfaces: ProviderInterfaces,
+    config: ProviderConfig,
+    providers: RefCell<Vec<RegisteredProvider>>,
+}
+
+impl Manager {
+    fn new(
+        app: Application,
+        interfaces: ProviderInterfaces,
+        config: ProviderConfig,
+        providers: Vec<RegisteredProvider>,
+    ) -> Self {
+        Self {
+            app,
+            interfaces,
+            config,
+            providers: RefCell::new(providers),
+        }
+    }
+
+    /// Find the search provider for the variant with the given `app_id`.
+    fn find_provider(&self, app_id: &str) -> Result<Rc<SearchProvider>, glib::Error> {
+        self.providers
+            .borrow()
+            .iter()
+            .find(|registered| registered.provider.app_id() == app_id)
+            .map(|registered| registered.provider.clone())
+            .ok_or_else(|| {
+                glib::Error::new(
+                    IOErrorEnum::NotFound,
+                    &format!("No search provider for variant {app_id}"),
+                )
+            })
+    }
+
+    /// List all search providers currently registered on the bus.
+    ///
+    /// Return the object path, the app ID, and the name of the app of every
+    /// search provider, in the order we registered them.
+    fn list_providers(&self) -> Vec<(glib::variant::ObjectPath, String, String)> {
+        self.providers
+            .borrow()
+            .iter()
+            .filter_map(|registered| {
+                let provider = &registered.provider;
+                let object_path =
+                    glib::variant::ObjectPath::try_from(provider.object_path.as_str()).ok()?;
+                Some((
+                    object_path,
+                    provider.app_id().to_owned(),
+                    provider.code_app_info.name().to_string(),
+                ))
+            })
+            .collect()
+    }
+
+    /// Scan for variants again, and serve search providers for new variants.
+    ///
+    /// Stop serving search providers for variants which are no longer installed.
+    /// Return the app IDs of all added and all removed variants.
+    fn rescan_variants(&self) -> Result<(Vec<String>, Vec<String>), glib::Error> {
+        let connection = self.app.dbus_connection().ok_or_else(|| {
+            glib::Error::new(IOErrorEnum::NotConnected, "Not connected to the bus")
+        })?;
+        let variants = all_variants();
+        let mut providers = self.providers.borrow_mut();
+
+        let mut removed = Vec::new();
+        let (kept, gone): (Vec<_>, Vec<_>) =
+            std::mem::take(&mut *providers)
+                .into_iter()
+                .partition(|registered| {
+                    let variant = &registered.provider.variant;
+                    variants
+                        .iter()
+                        .any(|candidate| candidate.app_id() == variant.app_id())
+                        && find_desktop_app_info(variant).is_some()
+                });
+        *providers = kept;
+        for registered in gone {
+            let app_id = registered.provider.app_id().to_owned();
+            glib::info!("Variant {app_id} is gone, no longer serving its search provider");
+            for id in registered.registration_ids {
+                if let Err(error) = connection.unregister_object(id) {
+                    glib::warn!("Failed to unregister search provider of {app_id}: {error}");
+                }
+            }
+            removed.push(app_id);
+        }
+
+        let mut added = Vec::new();
+        for variant in &variants {
+            let is_served = providers
+                .iter()
+                .any(|registered| registered.provider.app_id() == variant.app_id());
+            if !is_served {
+                if let Some(registered) = serve_variant(
+                    &self.app,
+                    &connection,
+                    &self.interfaces,
+                    self.config,
+                    variant,
+                ) {
+                    added.push(variant.app_id().to_owned());
+                    providers.push(registered);
+                }
+            }
+        }
+        Ok((added, removed))
+    }
+
+    /// Handle the given manager method `call`.
+    async fn handle_call(&self, call: ManagerMethod) -> Result<Option<Variant>, glib::Error> {
+        // Hold on to the application while we're processing a DBus call.
+        let _guard = self.app.hold();
+        match call {
+            ManagerMethod::OpenInVariant(OpenInVariant(app_id, uri)) => {
+                glib::debug!("Opening {uri} in {app_id}");
+                self.find_provider(&app_id)?
+                    .launch_uri(Some(uri.as_str()))
+                    .await?;
+                Ok(None)
+            }
+            ManagerMethod::OpenContainingFolder(OpenContainingFolder(uri)) => {
+                let folder = containing_folder(&uri)?;
+                glib::debug!("Opening {folder}, containing {uri}");
+                gio::AppInfo::launch_default_for_uri_future(
+                    &folder,
+                    None::<&gio::AppLaunchContext>,
+                )
+                .await?;
+                Ok(None)
+            }
+            ManagerMethod::RescanVariants(RescanVariants) => {
+                let (added, removed) = self.rescan_variants()?;
+                glib::info!("Rescanned variants, added {added:?}, removed {removed:?}");
+                Ok(Some((added, removed).to_variant()))
+            }
+            ManagerMethod::GetInfo(GetInfo) => {
+                let variants = self
+                    .providers
+                    .borrow()
+                    .iter()
+                    .map(|registered| registered.provider.app_id().to_owned())
+                    .collect::<Vec<_>>();
+                let commit = GIT_COMMIT.unwrap_or_default();
+                Ok(Some(
+                    (env!("CARGO_PKG_VERSION"), commit, variants).to_variant(),
+                ))
+            }
+            ManagerMethod::ListProviders(ListProviders) => {
+                Ok(Some((self.list_providers(),).to_variant()))
+            }
+        }
+    }
+
+    /// Register this manager under `object_path` on a D-Bus `connection`.
+    ///
+    /// Consume the manager, as it gets moved into the callback closure for
+    /// D-Bus invocations.
+    fn register(
+        self,
+        connection: &gio::DBusConnection,
+        object_path: &str,
+        interface_info: &DBusInterfaceInfo,
+    ) -> Result<gio::RegistrationId, glib::Error> {
+        let manager = Rc::new(self);
+        connection
+            .register_object(object_path, interface_info)
+            .typed_method_call::<ManagerMethod>()
+            .invoke_and_return_future_local(move |_, _, call| {
+                let manager = manager.clone();
+                async move { manager.handle_call(call).await }
+            })
+            .build()
+    }
+}
+
+/// Whether the workspace configuration at `config_path` belongs to an untitled workspace.
+///
+/// Visual Studio Code stores configurations of untitled workspaces in hashed
+/// directories of its own choice, either as `.code-workspace` files under
+/// `User/workspaceStorage`, or as `workspace.json` under `Workspaces`.  These paths
+/// mean nothing to users.
+fn is_untitled_workspace(config_path: &str) -> bool {
+    (config_path.contains("/User/workspaceStorage/") && config_path.ends_with(".code-workspace"))
+        || (config_path.contains("/Workspaces/") && config_path.ends_with("/workspace.json"))
+}
+
+/// The default for the maximum number of workspaces to load from a database.
+///
+/// Large enough to still find older projects, but small enough to bound the
+/// memory and time spent on users with thousands of recent workspaces.
+const DEFAULT_MAX_WORKSPACES: usize = 1000;
+
+/// Get the maximum number of workspaces to load from a database.
+///
+/// Read `$VSCODE_SEARCH_PROVIDER_MAX_WORKSPACES`, and fall back to
+/// [`DEFAULT_MAX_WORKSPACES`] if unset, invalid or zero.
+fn max_workspaces() -> usize {
+    positive_number_from_environment("VSCODE_SEARCH_PROVIDER_MAX_WORKSPACES")
+        .unwrap_or(DEFAULT_MAX_WORKSPACES)
+}
+
+/// Whether `name` matches the glob `pattern`.
+///
+/// In `pattern`, `*` matches any sequence of characters, `?` matches any
+/// single character, and all other characters match themselves.
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let name = name.chars().collect::<Vec<_>>();
+    let (mut p, mut n) = (0, 0);
+    // The position of the last `*` in pattern, and of the character in name it
+    // currently matches up to.
+    let mut backtrack = None;
+    while n < name.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if let Some((star, matched)) = backtrack {
+            backtrack = Some((star, matched + 1));
+            p = star + 1;
+            n = matched + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Get the patterns of recently opened files to load as workspaces.
+///
+/// Read `$VSCODE_SEARCH_PROVIDER_FILE_PATTERNS`, a list of glob patterns
+/// separated by commas, see [`glob_matches`].  Ignore whitespace around each
+/// pattern, and empty patterns.
+fn file_patterns() -> Vec<String> {
+    std::env::var("VSCODE_SEARCH_PROVIDER_FILE_PATTERNS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|pattern| !pattern.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+/// Whether the recently opened file `uri` matches any of `file_patterns`.
+///
+/// Match the patterns against the decoded name of the file only.
+fn is_matching_file(file_patterns: &[String], uri: &str) -> bool {
+    let path = glib::Uri::parse(uri, UriFlags::NONE).map_or_else(
+        |_| percent_decode_name(strip_query_and_fragment(uri)),
+        |parsed_uri| parsed_uri.path().into(),
+    );
+    let name = name_from_uri(&path).unwrap_or_default();
+    file_patterns
+        .iter()
+        .any(|pattern| glob_matches(pattern, name))
+}
+
+/// Get the paths to exclude workspaces under.
+///
+/// Read `$VSCODE_SEARCH_PROVIDER_EXCLUDE`, a list of paths separated by colons,
+/// and expand each path, see [`xdg::expand_path`].  Ignore empty paths.
+fn excluded_paths() -> Vec<PathBuf> {
+    std::env::var("VSCODE_SEARCH_PROVIDER_EXCLUDE")
+        .unwrap_or_default()
+        .split(':')
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .map(xdg::expand_path)
+        .collect()
+}
+
+/// Whether the local workspace `uri` is under any of `excluded_paths`.
+///
+/// Never exclude remote workspaces.
+fn is_excluded(excluded_paths: &[PathBuf], uri: &str) -> bool {
+    gio::File::for_uri(uri).path().is_some_and(|path| {
+        excluded_paths
+            .iter()
+            .any(|excluded| path.starts_with(excluded))
+    })
+}
+
+/// Whether the local workspace `uri` is hidden, or under a hidden directory.
+///
+/// A file or directory is hidden if its name starts with `.`, e.g. `~/.config`.
+/// Never consider remote workspaces hidden.
+fn is_hidden(uri: &str) -> bool {
+    gio::File::for_uri(uri).path().is_some_and(|path| {
+        path.components().any(|component| match component {
+            Component::Normal(name) => name.as_encoded_bytes().starts_with(b"."),
+            _ => false,
+        })
+    })
+}
+
+/// Options for loading workspaces from a database.
+struct LoadOptions<'a> {
+    /// The key of the list of recently opened paths, see [`CodeVariant::storage_key`].
+    storage_key: &'a str,
+    /// How many workspaces to load at most, see [`max_workspaces`].
+    limit: usize,
+    /// Which recently opened files to load, see [`file_patterns`].
+    file_patterns: Vec<String>,
+    /// Which local workspaces to skip, see [`excluded_paths`].
+    excluded_paths: Vec<PathBuf>,
+    /// Whether to skip hidden local workspaces, see [`is_hidden`].
+    skip_hidden: bool,
+}
+
+impl<'a> LoadOptions<'a> {
+    /// Read options to load workspaces under `storage_key` from the environment.
+    fn from_environment(storage_key: &'a str) -> Self {
+        Self {
+            storage_key,
+            limit: max_workspaces(),
+            file_patterns: file_patterns(),
+            excluded_paths: excluded_paths(),
+            skip_hidden: environment_flag("VSCODE_SEARCH_PROVIDER_SKIP_HIDDEN"),
+        }
+    }
+}
+
+/// Load workspaces from the given connection, according to `options`.
+///
+/// Visual Studio Code stores the most recent workspaces first, so keep the
+/// [`LoadOptions::limit`] most recent workspaces.  Skip untitled workspaces, see
+/// [`is_untitled_workspace`].  Skip recently opened files, unless they match
+/// any file pattern of `options`, see [`is_matching_file`], and skip workspaces
+/// under excluded paths of `options`, see [`is_excluded`].  If `options` asks to,
+/// skip hidden workspaces, see [`is_hidden`].
+fn load_workspaces(
+    connection: &rusqlite::Connection,
+    options: &LoadOptions,
+) -> Result<Vec<String>, glib::Error> {
+    Ok(
+        query_recently_opened_path_lists(connection, options.storage_key)?
+            .unwrap_or_default()
+            .entries
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| match entry {
+                StorageOpenedPathsListEntry::Workspace { workspace } => {
+                    if is_untitled_workspace(&workspace.config_path) {
+                        glib::debug!("Skipping untitled workspace {}", workspace.config_path);
+                        None
+                    } else {
+                        Some(workspace.config_path)
+                    }
+                }
+                StorageOpenedPathsListEntry::Folder { uri } => Some(uri),
+                StorageOpenedPathsListEntry::File { uri } => {
+                    Some(uri).filter(|uri| is_matching_file(&options.file_patterns, uri))
+                }
+            })
+            .filter(|uri| {
+                let excluded = is_excluded(&options.excluded_paths, uri);
+                if excluded {
+                    glib::debug!("Skipping excluded workspace {uri}");
+                }
+                !excluded
+            })
+            .filter(|uri| {
+                let hidden = options.skip_hidden && is_hidden(uri);
+                if hidden {
+                    glib::debug!("Skipping hidden workspace {uri}");
+                }
+                !hidden
+            })
+            .take(options.limit)
+            .collect(),
+    )
+}
+
+/// Get the URI of an entry in the list of recently opened paths, if any.
+#[cfg(feature = "forget-workspace")]
+fn entry_uri(entry: &serde_json::Value) -> Option<&str> {
+    entry
+        .get("folderUri")
+        .or_else(|| entry.get("fileUri"))
+        .or_else(|| entry.get("workspace")?.get("configPath"))
+        .and_then(serde_json::Value::as_str)
+}
+
+/// Remove the workspace `uri` from the database at `db_path`.
+///
+/// Open `db_path` read-write, and remove all entries equivalent to `uri` from the
+/// list of recently opened paths, in a single transaction, keeping all other
+/// entries as they are.  Return whether we removed any entry.
+#[cfg(feature = "forget-workspace")]
+fn forget_workspace_in_database(
+    db_path: &Path,
+    storage_key: &str,
+    uri: &str,
+) -> Result<bool, glib::Error> {
+    let to_error = |error: rusqlite::Error| {
+        glib::Error::new(
+            IOErrorEnum::Failed,
+            &format!(
+                "Failed to forget workspace in {}: {error}",
+                db_path.display()
+            ),
+        )
+    };
+    let mut connection = rusqlite::Connection::open_with_flags(
+        db_path,
+        OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(to_error)?;
+    let transaction = connection.transaction().map_err(to_error)?;
+    let value: Option<serde_json::Value> = transaction
+        .query_row(
+            "SELECT value FROM ItemTable WHERE key = ?1;",
+            [storage_key],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(to_error)?;
+    let Some(value) = value else {
+        return Ok(false);
+    };
+    // Write the list back in the shape we found it in, see
+    // query_recently_opened_path_lists.
+    let encoded_twice = value.is_string();
+    let mut value = decode_double_encoded_json(value).map_err(|error| {
+        glib::Error::new(
+            IOErrorEnum::InvalidData,
+            &format!("Failed to deserialize recently opened path lists: {error}"),
+        )
+    })?;
+    let Some(entries) = unwrap_list(&mut value)
+        .get_mut("entries")
+        .and_then(serde_json::Value::as_array_mut)
+    else {
+        return Ok(false);
+    };
+    let id = canonical_uri(uri);
+    let count = entries.len();
+    entries.retain(|entry| entry_uri(entry).map_or(true, |uri| canonical_uri(uri) != id));
+    if entries.len() == count {
+        return Ok(false);
+    }
+    if encoded_twice {
+        value = serde_json::Value::String(value.to_string());
+    }
+    transaction
+        .execute(
+            "UPDATE ItemTable SET value = ?1 WHERE key = ?2;",
+            rusqlite::params![&value, storage_key],
+        )
+        .map_err(to_error)?;
+    transaction.commit().map_err(to_error)?;
+    Ok(true)
+}
+
+/// Load workspaces from the database at `db_path`, see [`load_workspaces`].
+///
+/// Fail with a clear error if `db_path` exists but is no regular file, e.g. a
+/// directory, instead of the rather confusing error of sqlite.
+fn load_workspaces_from_path(
+    db_path: &Path,
+    options: &LoadOptions,
+) -> Result<Vec<String>, glib::Error> {
+    if let Ok(metadata) = std::fs::metadata(db_path) {
+        if metadata.is_dir() {
+            return Err(glib::Error::new(
+                IOErrorEnum::IsDirectory,
+                &format!("Database {} is a directory", db_path.display()),
+            ));
+        } else if !metadata.is_file() {
+            return Err(glib::Error::new(
+                IOErrorEnum::NotRegularFile,
+                &format!("Database {} is no regular file", db_path.display()),
+            ));
+        }
+    }
+    open_connection(db_path).and_then(|connection| load_workspaces(&connection, options))
+}
+
+/// Load workspaces from all databases at `db_paths`, see [`CodeVariant::database_paths`].
+///
+/// Fail if the first, i.e. the default, database fails to load; skip other
+/// databases which fail to load.  Return all workspace URIs in order of the
+/// databases; [`Workspaces::new`] removes duplicates.
+///
+/// Load workspaces from each database according to [`LoadOptions::from_environment`],
+/// and read the list of recently opened paths under `storage_key`, see
+/// [`CodeVariant::storage_key`].
+fn load_workspaces_from_databases(
+    db_paths: &[PathBuf],
+    storage_key: &str,
+) -> Result<Vec<String>, glib::Error> {
+    let options = LoadOptions::from_environment(storage_key);
+    let (default_path, profile_paths) = db_paths
+        .split_first()
+        .ok_or_else(|| glib::Error::new(IOErrorEnum::NotFound, "No workspace database to load"))?;
+    let mut uris = load_workspaces_from_path(default_path, &options)?;
+    for path in profile_paths {
+        match load_workspaces_from_path(path, &options) {
+            Ok(profile_uris) => uris.extend(profile_uris),
+            Err(error) => {
+                glib::warn!(
+                    "Skipping profile database {}, failed to load workspaces: {error}",
+                    path.display()
+                );
+            }
+        }
+    }
+    Ok(uris)
+}
+
+/// How long to wait for a command to list workspaces, see [`load_workspaces_from_command`].
+const WORKSPACES_COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Load workspaces from the output of `command`.
+///
+/// Run `command` without arguments, and return every non-empty line of its
+/// standard output as a workspace URI, in order.  Fail if `command` fails, or
+/// kill it and fail if it does not finish within `timeout`.
+///
+/// Wait for `command` on the thread-default main context, so that the main
+/// loop keeps serving D-Bus calls while `command` runs.
+async fn load_workspaces_from_command(
+    command: &Path,
+    timeout: Duration,
+) -> Result<Vec<String>, glib::Error> {
+    let process = gio::Subprocess::newv(&[command.as_os_str()], gio::SubprocessFlags::STDOUT_PIPE)?;
+    let Ok(output) =
+        glib::future_with_timeout(timeout, process.communicate_utf8_future(None)).await
+    else {
+        process.force_exit();
+        return Err(glib::Error::new(
+            IOErrorEnum::TimedOut,
+            &format!("Command did not finish within {timeout:?}"),
+        ));
+    };
+    let (stdout, _) = output?;
+    if !process.is_successful() {
+        return Err(glib::Error::new(
+            IOErrorEnum::Failed,
+            &format!("Command exited with status {}", process.exit_status()),
+        ));
+    }
+    Ok(stdout
+        .as_deref()
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(ToOwned::to_owned)
+        .collect())
+}
+
+/// Load all workspaces of `variant` from all databases at `db_paths`.
+///
+/// See [`load_workspaces_from_databases`].  With `use_cache` serve workspaces
+/// from the persistent cache if the databases at `db_paths` still have the
+/// given `stamps`, and otherwise load workspaces and cache them, see
+/// [`CachedWorkspaces`].  Never cache workspaces if the default database does
+/// not exist.
+///
+/// This does not include workspaces from the workspaces command of `variant`,
+/// see [`SearchProvider::spawn_workspaces_command`].
+fn load_cached_workspaces_of_variant(
+    variant: &CodeVariant,
+    db_paths: &[PathBuf],
+    stamps: &[Option<FileStamp>],
+    use_cache: bool,
+) -> Result<Vec<String>, glib::Error> {
+    if !use_cache || stamps.first().map_or(true, Option::is_none) {
+        return load_workspaces_from_databases(db_paths, &variant.storage_key());
+    }
+    if let Some(uris) = CachedWorkspaces::read(variant.app_id(), stamps) {
+        glib::debug!("Using cached workspaces of {}", variant.app_id());
+        return Ok(uris);
+    }
+    let uris = load_workspaces_from_databases(db_paths, &variant.storage_key())?;
+    CachedWorkspaces::write(variant.app_id(), stamps, &uris);
+    Ok(uris)
+}
+
+/// Whether `path` resides on a read-only filesystem.
+///
+/// If we fail to tell, assume the filesystem is writable.
+fn is_on_read_only_filesystem(path: &Path) -> bool {
+    gio::File::for_path(path)
+        .query_filesystem_info(
+            gio::FILE_ATTRIBUTE_FILESYSTEM_READONLY,
+            gio::Cancellable::NONE,
+        )
+        .is_ok_and(|info| info.boolean(gio::FILE_ATTRIBUTE_FILESYSTEM_READONLY))
+}
+
+/// Get the sqlite URI to open the database at `db_path` as immutable.
+///
+/// sqlite then never writes to the database, nor locks it, see
+/// <https://www.sqlite.org/uri.html#uriimmutable>.
+fn immutable_database_uri(db_path: &Path) -> Result<String, glib::Error> {
+    glib::filename_to_uri(db_path, None).map(|uri| format!("{uri}?immutable=1"))
+}
+
+/// Open a read-only connection to the database at `db_path`.
+///
+/// Even for read-only connections sqlite may try to create `-wal` and `-shm`
+/// files next to the database, which fails on read-only filesystems such as
+/// snapshots.  Open databases on read-only filesystems as immutable instead,
+/// to prevent any attempt to write, see [`immutable_database_uri`].
+fn open_connection<P: AsRef<Path>>(db_path: P) -> Result<rusqlite::Connection, glib::Error> {
+    let mut flags = OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+    let mut target = db_path.as_ref().to_path_buf();
+    if is_on_read_only_filesystem(db_path.as_ref()) {
+        match immutable_database_uri(db_path.as_ref()) {
+            Ok(uri) => {
+                glib::debug!(
+                    "{} is on a read-only filesystem, opening as immutable",
+                    db_path.as_ref().display()
+                );
+                flags |= OpenFlags::SQLITE_OPEN_URI;
+                target = PathBuf::from(uri);
+            }
+            Err(error) => glib::warn!(
+                "Failed to convert {} to URI, opening normally: {error}",
+                db_path.as_ref().display()
+            ),
+        }
+    }
+    rusqlite::Connection::open_with_flags(target, flags).map_err(|error| {
+        glib::Error::new(
+            IOErrorEnum::Failed,
+            &format!(
+                "Failed to open connection to {}: {error}",
+                db_path.as_ref().display()
+            ),
+        )
+    })
+}
+
+/// An argument of a launch template, see [`CodeVariant::launch_template`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LaunchArg {
+    /// A literal argument.
+    Literal(&'static str),
+    /// The path to the desktop file of the variant.
+    DesktopFile,
+    /// The command line of the desktop entry of the variant, without field codes.
+    Command,
+    /// Additional options of the variant, e.g. restricted mode options.
+    Options,
+    /// The URI to open; omitted when launching the app without any URI.
+    Uri,
+    /// The URI to open, as `--file-uri` for workspace files or as `--folder-uri`
+    /// otherwise; omitted when launching the app without any URI.
+    OpenUri,
+}
+
+impl LaunchArg {
+    /// Whether this argument expands to the URI to open.
+    fn is_uri(self) -> bool {
+        matches!(self, Self::Uri | Self::OpenUri)
+    }
+}
+
+/// Launch a variant through its desktop file with `gio launch`.
+const GIO_LAUNCH_TEMPLATE: &[LaunchArg] = &[
+    LaunchArg::Literal("/usr/bin/gio"),
+    LaunchArg::Literal("launch"),
+    LaunchArg::DesktopFile,
+    LaunchArg::Uri,
+];
+
+/// Launch a variant directly with the command line of its desktop entry.
+///
+/// Unlike [`GIO_LAUNCH_TEMPLATE`] this supports additional options, and opens
+/// URIs with `--folder-uri` or `--file-uri`, see [`open_uri_option`], which
+/// restores the window state of the workspace like reopening it from within
+/// Visual Studio Code does.
+const CLI_LAUNCH_TEMPLATE: &[LaunchArg] =
+    &[LaunchArg::Command, LaunchArg::Options, LaunchArg::OpenUri];
+
+/// Validate a launch `template`.
+///
+/// A template must start with a literal program to run or the command of the
+/// desktop entry, and must contain the URI exactly once.
+fn validate_launch_template(template: &[LaunchArg]) -> Result<(), glib::Error> {
+    if !matches!(
+        template.first(),
+        Some(LaunchArg::Literal(_) | LaunchArg::Command)
+    ) {
+        return Err(glib::Error::new(
+            IOErrorEnum::InvalidArgument,
+            "Launch template does not start with a program",
+        ));
+    }
+    match template.iter().filter(|arg| arg.is_uri()).count() {
+        0 => Err(glib::Error::new(
+            IOErrorEnum::InvalidArgument,
+            "Launch template contains no URI",
+        )),
+        1 => Ok(()),
+        _ => Err(glib::Error::new(
+            IOErrorEnum::InvalidArgument,
+            "Launch template contains more than one URI",
+        )),
+    }
+}
+
+/// Get the command line of the desktop entry `app_info`, without field codes.
+///
+/// Remove all field codes like `%F`, as well as the file forwarding markers of
+/// Flatpak, i.e. `@@` and `@@u`, since we pass the URI on our own.
+fn desktop_entry_command(app_info: &DesktopAppInfo) -> Result<Vec<OsString>, glib::Error> {
+    let commandline = app_info.commandline().ok_or_else(|| {
+        glib::Error::new(IOErrorEnum::NotFound, "Desktop entry has no command line")
+    })?;
+    let command = glib::shell_parse_argv(commandline)?
+        .into_iter()
+        .filter(|arg| {
+            let arg = arg.to_string_lossy();
+            !(arg.starts_with('%') || arg == "@@" || arg == "@@u")
+        })
+        .collect();
+    Ok(command)
+}
+
+/// What a workspace URI refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WorkspaceKind {
+    /// A folder.
+    Folder,
+    /// A single file.
+    File,
+    /// A workspace file, i.e. a multi-root workspace.
+    Workspace,
+}
+
+impl WorkspaceKind {
+    /// Tell what `uri` refers to.
+    ///
+    /// Tell workspace files by their extension, see [`WORKSPACE_EXTENSION`], and
+    /// other files by looking at local files.  Assume that all other URIs,
+    /// including all remote URIs, refer to folders.
+    fn of_uri(uri: &str) -> Self {
+        let file = gio::File::for_uri(uri);
+        if uri.ends_with(WORKSPACE_EXTENSION) {
+            Self::Workspace
+        } else if file.is_native()
+            && file.query_file_type(gio::FileQueryInfoFlags::NONE, gio::Cancellable::NONE)
+                == gio::FileType::Regular
+        {
+            Self::File
+        } else {
+            Self::Folder
+        }
+    }
+
+    /// A label for this kind, to show to users.
+    fn label(self) -> &'static str {
+        match self {
+            Self::Folder => "Folder",
+            Self::File => "File",
+            Self::Workspace => "Workspace",
+        }
+    }
+}
+
+/// Get the command line option of Visual Studio Code to open `uri` with.
+///
+/// Return `--file-uri` for workspace files and other local files, and
+/// `--folder-uri` otherwise, see [`WorkspaceKind::of_uri`].  Visual Studio Code
+/// then restores the window state of the workspace, just like when reopening it
+/// from within the app.
+fn open_uri_option(uri: &str) -> &'static str {
+    match WorkspaceKind::of_uri(uri) {
+        WorkspaceKind::Folder => "--folder-uri",
+        WorkspaceKind::File | WorkspaceKind::Workspace => "--file-uri",
+    }
+}
+
+/// Arguments to substitute into a launch template, see [`expand_launch_template`].
+struct LaunchTemplateArgs<'a> {
+    /// The path to the desktop file of the variant.
+    desktop_file: &'a Path,
+    /// The command line of the desktop entry of the variant, see [`desktop_entry_command`].
+    command: &'a [OsString],
+    /// Additional options of the variant.
+    options: &'a [String],
+    /// The URI to open, if any.
+    uri: Option<&'a str>,
+}
+
+/// Expand a launch `template` into a command line.
+///
+/// Substitute `args` into `template`; if there is no URI omit the URI argument
+/// altogether, to launch the app without any URI.
+fn expand_launch_template<'a>(
+    template: &'a [LaunchArg],
+    args: &LaunchTemplateArgs<'a>,
+) -> Vec<&'a OsStr> {
+    let mut command = Vec::with_capacity(template.len() + args.command.len());
+    for arg in template {
+        match arg {
+            LaunchArg::Literal(literal) => command.push(OsStr::new(literal)),
+            LaunchArg::DesktopFile => command.push(args.desktop_file.as_os_str()),
+            LaunchArg::Command => command.extend(args.command.iter().map(OsString::as_os_str)),
+            LaunchArg::Options => command.extend(args.options.iter().map(OsStr::new)),
+            LaunchArg::Uri => command.extend(args.uri.map(OsStr::new)),
+            LaunchArg::OpenUri => {
+                if let Some(uri) = args.uri {
+                    command.extend([OsStr::new(open_uri_option(uri)), OsStr::new(uri)]);
+                }
+            }
+        }
+    }
+    command
+}
+
+/// A variant of Visual Studio Code whose recent workspaces we expose.
+///
+/// See [`VARIANTS`] for all known variants, and [`discover_variants`] for
+/// unknown variants.
+#[derive(Debug, Clone)]
+struct CodeVariant {
+    /// The desktop ID of this variant.
+    desktop_id: Cow<'static, str>,
+    /// The name of the configuration directory of this variant under `$XDG_CONFIG_HOME`.
+    config_directory_name: Cow<'static, str>,
+    /// The command to launch this variant with by default, inside a new systemd scope.
+    ///
+    /// See [`expand_launch_template`] and [`CodeVariant::configured_launch_template`].
+    launch_template: &'static [LaunchArg],
+    /// Options to open workspaces of this variant in restricted mode.
+    ///
+    /// See [`CodeVariant::launch_options`].
+    restricted_options: &'static [&'static str],
+    /// The name of a themed icon for results of this variant, if its desktop
+    /// entry has no icon.
+    fallback_icon: &'static str,
+    /// The desktop IDs of Flatpak packages of this variant.
+    ///
+    /// Flatpak exports desktop entries under the ID of the Flatpak app, e.g.
+    /// `com.visualstudio.code.desktop`, not under the ID of the native entry.
+    flatpak_desktop_ids: &'static [&'static str],
+}
+
+impl CodeVariant {
+    /// The app ID of this variant, i.e. its desktop ID without `.desktop`.
+    fn app_id(&self) -> &str {
+        self.desktop_id.trim_end_matches(".desktop")
+    }
+
+    /// The name of the environment variable to configure `setting` for this variant.
+    ///
+    /// Return `VSCODE_SEARCH_PROVIDER_<APPID>_<SETTING>`, where `<APPID>` is the
+    /// upper-cased app ID, with all non-alphanumeric characters replaced by `_`.
+    fn environment_variable(&self, setting: &str) -> String {
+        let app_id = self
+            .app_id()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+        format!("VSCODE_SEARCH_PROVIDER_{app_id}_{setting}")
+    }
+
+    /// Get the path to the global storage database of this variant.
+    ///
+    /// If `$VSCODE_SEARCH_PROVIDER_<APPID>_DB` is set return its value, expanded
+    /// with [`xdg::expand_path`]; otherwise return the `state.vscdb` database in
+    /// the configuration directory of this variant, see [`CodeVariant::config_directory`].
+    fn database_path(&self) -> PathBuf {
+        xdg::path_from_environment(&self.environment_variable("DB")).unwrap_or_else(|| {
+            self.config_directory()
+                .join("User")
+                .join("globalStorage")
+                .join("state.vscdb")
+        })
+    }
+
+    /// Get the launch template for this variant.
+    ///
+    /// Use `$VSCODE_SEARCH_PROVIDER_<APPID>_LAUNCH` to select a template, either
+    /// `gio` for [`GIO_LAUNCH_TEMPLATE`] or `cli` for [`CLI_LAUNCH_TEMPLATE`];
+    /// otherwise use the default launch template of this variant.
+    fn configured_launch_template(&self) -> &'static [LaunchArg] {
+        let variable = self.environment_variable("LAUNCH");
+        match std::env::var(&variable).as_deref() {
+            Ok("gio") => GIO_LAUNCH_TEMPLATE,
+            Ok("cli") => CLI_LAUNCH_TEMPLATE,
+            Ok(value) => {
+                glib::warn!("Ignoring unknown launch template {value} in ${variable}");
+                self.launch_template
+            }
+            Err(_) => self.launch_template,
+        }
+    }
+
+    /// Get additional options to launch this variant with.
+    ///
+    /// If `$VSCODE_SEARCH_PROVIDER_<APPID>_RESTRICTED` is `1` return the restricted
+    /// mode options of this variant, e.g. to audit unfamiliar projects without
+    /// running any extensions.
+    ///
+    /// If `$VSCODE_SEARCH_PROVIDER_<APPID>_PROFILE` is set, add `--profile` with
+    /// its value, to open workspaces in the given profile of this variant.
+    fn launch_options(&self) -> Vec<String> {
+        let mut options = Vec::new();
+        if environment_flag(&self.environment_variable("RESTRICTED")) {
+            options.extend(
+                self.restricted_options
+                    .iter()
+                    .map(|option| (*option).to_owned()),
+            );
+        }
+        match std::env::var(self.environment_variable("PROFILE")) {
+            Ok(profile) if !profile.is_empty() => {
+                options.extend(["--profile".to_owned(), profile]);
+            }
+            _ => {}
+        }
+        options
+    }
+
+    /// Get the key of the list of recently opened paths in the database of this variant.
+    ///
+    /// Read the key from `$VSCODE_SEARCH_PROVIDER_<APPID>_STORAGE_KEY`, for forks
+    /// which store recent workspaces under a different key, and fall back to
+    /// [`RECENTLY_OPENED_PATHS_LIST_KEY`].
+    fn storage_key(&self) -> Cow<'static, str> {
+        std::env::var(self.environment_variable("STORAGE_KEY"))
+            .map_or(Cow::Borrowed(RECENTLY_OPENED_PATHS_LIST_KEY), Cow::Owned)
+    }
+
+    /// Get the command which lists additional workspaces of this variant, if any.
+    ///
+    /// Read the path of the command from `$VSCODE_SEARCH_PROVIDER_<APPID>_WORKSPACES_COMMAND`,
+    /// see [`load_workspaces_from_command`].
+    fn workspaces_command(&self) -> Option<PathBuf> {
+        xdg::path_from_environment(&self.environment_variable("WORKSPACES_COMMAND"))
+    }
+
+    /// Get the paths of all databases to load workspaces from.
+    ///
+    /// Always return the default database first, see [`CodeVariant::database_path`].
+    /// If `$VSCODE_SEARCH_PROVIDER_<APPID>_PROFILES` is `1`, additionally return
+    /// the databases of all profiles, i.e. `User/profiles/*/globalStorage/state.vscdb`
+    /// in the configuration directory, in a stable order.
+    fn database_paths(&self) -> Vec<PathBuf> {
+        let mut paths = vec![self.database_path()];
+        if environment_flag(&self.environment_variable("PROFILES")) {
+            let profiles_directory = self.config_directory().join("User").join("profiles");
+            let mut profile_paths = std::fs::read_dir(&profiles_directory)
+                .map(|entries| {
+                    entries
+                        .filter_map(Result::ok)
+                        .map(|entry| entry.path().join("globalStorage").join("state.vscdb"))
+                        .filter(|path| path.is_file())
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default();
+            profile_paths.sort();
+            paths.extend(profile_paths);
+        }
+        paths
+    }
+
+    /// Get the configuration directory of this variant.
+    ///
+    /// If `$VSCODE_SEARCH_PROVIDER_<APPID>_CONFIG_DIR` is set to an absolute
+    /// path, after expanding it with [`xdg::expand_path`], return it, e.g. for
+    /// `--user-data-dir` setups; otherwise return the directory of this variant
+    /// under `$XDG_CONFIG_HOME`.  Ignore relative overrides, because they would
+    /// depend on the working directory.
+    fn config_directory(&self) -> PathBuf {
+        let variable = self.environment_variable("CONFIG_DIR");
+        match xdg::path_from_environment(&variable) {
+            Some(directory) if directory.is_absolute() => directory,
+            Some(directory) => {
+                glib::warn!(
+                    "Ignoring ${variable}: {} is not an absolute path",
+                    directory.display()
+                );
+                glib::user_config_dir().join(self.config_directory_name.as_ref())
+            }
+            None => glib::user_config_dir().join(self.config_directory_name.as_ref()),
+        }
+    }
+}
+
+/// Directories Flatpak exports desktop entries of installed apps to.
+///
+/// Flatpak exports desktop entries of user installations to `$XDG_DATA_HOME`, and
+/// of system installations to `/var/lib/flatpak`.  Neither directory is part of
+/// the default XDG data directories.
+fn flatpak_application_dirs() -> [PathBuf; 2] {
+    [
+        glib::user_data_dir().join("flatpak/exports/share/applications"),
+        PathBuf::from("/var/lib/flatpak/exports/share/applications"),
+    ]
+}
+
+/// Whether GNOME Shell can likely show `icon`.
+///
+/// Every icon theme falls back to the hicolor theme, where apps install their
+/// icons, so accept themed icons only if any of their names exists in the
+/// hicolor theme in the XDG data directories or the export directories of
+/// Flatpak, see [`xdg::has_hicolor_icon`].  Accept all other icons, e.g. icon
+/// files.
+fn is_resolvable_icon(icon: &gio::Icon) -> bool {
+    icon.downcast_ref::<gio::ThemedIcon>().map_or(true, |icon| {
+        let mut data_dirs = xdg::data_dirs();
+        data_dirs.extend(
+            flatpak_application_dirs()
+                .iter()
+                .filter_map(|directory| directory.parent().map(Path::to_path_buf)),
+        );
+        icon.names()
+            .iter()
+            .any(|name| xdg::has_hicolor_icon(&data_dirs, name))
+    })
+}
+
+/// Find the desktop entry of `variant`.
+///
+/// Look up the desktop ID of `variant` in XDG data directories first, so that
+/// native entries take precedence, and then its Flatpak desktop IDs in the
+/// export directories of Flatpak, see [`find_flatpak_desktop_app_info`].  If
+/// neither exists, e.g. for repackaged variants, fall back to
+/// [`find_desktop_app_info_by_app_id`].
+fn find_desktop_app_info(variant: &CodeVariant) -> Option<DesktopAppInfo> {
+    DesktopAppInfo::new(&variant.desktop_id)
+        .or_else(|| {
+            find_flatpak_desktop_app_info(variant.flatpak_desktop_ids, &flatpak_application_dirs())
+        })
+        .or_else(|| find_desktop_app_info_by_app_id(variant.app_id()))
+}
+
+/// Find the first of `desktop_ids` in the Flatpak export `directories`.
+///
+/// Search `directories` in order, i.e. user installations before system
+/// installations, see [`flatpak_application_dirs`], and all `desktop_ids` in
+/// order in each directory.
+fn find_flatpak_desktop_app_info(
+    desktop_ids: &[&str],
+    directories: &[PathBuf],
+) -> Option<DesktopAppInfo> {
+    directories.iter().find_map(|directory| {
+        desktop_ids
+            .iter()
+            .find_map(|desktop_id| DesktopAppInfo::from_filename(directory.join(desktop_id)))
+    })
+}
+
+/// Whether the desktop entry `app_info` refers to the given `app_id`.
+///
+/// That is, whether its `StartupWMClass` matches `app_id` ignoring case, or
+/// its executable is named `app_id`.
+fn refers_to_app_id(app_info: &DesktopAppInfo, app_id: &str) -> bool {
+    app_info
+        .startup_wm_class()
+        .is_some_and(|wm_class| wm_class.eq_ignore_ascii_case(app_id))
+        || app_info
+            .executable()
+            .file_name()
+            .is_some_and(|name| name == app_id)
+}
+
+/// Find any desktop entry which refers to the given `app_id`.
+///
+/// Look for a desktop entry which refers to `app_id`, see [`refers_to_app_id`].
+/// If multiple entries match, pick the one with the lowest desktop ID, for
+/// determinism.
+fn find_desktop_app_info_by_app_id(app_id: &str) -> Option<DesktopAppInfo> {
+    let mut candidates = gio::AppInfo::all()
+        .into_iter()
+        .filter_map(|app_info| app_info.downcast::<DesktopAppInfo>().ok())
+        .filter(|app_info| refers_to_app_id(app_info, app_id))
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(AppInfoExt::id);
+    let app_info = candidates.into_iter().next()?;
+    glib::info!(
+        "Found no desktop entry for {app_id}, using {} instead",
+        app_info.id().unwrap_or_default()
+    );
+    Some(app_info)
+}
+
+/// All variants of Visual Studio Code we support.
+const VARIANTS: [CodeVariant; 3] = [
+    // The standard Arch Linux code package from community
+    CodeVariant {
+        desktop_id: Cow::Borrowed("code-oss.desktop"),
+        config_directory_name: Cow::Borrowed("Code - OSS"),
+        launch_template: GIO_LAUNCH_TEMPLATE,
+        restricted_options: &["--disable-extensions"],
+        fallback_icon: "com.visualstudio.code.oss",
+        flatpak_desktop_ids: &["com.visualstudio.code-oss.desktop"],
+    },
+    // The standard codium package on Linux from here: https://github.com/VSCodium/vscodium.
+    // Should work for most Linux distributions packaged from here.
+    CodeVariant {
+        desktop_id: Cow::Borrowed("codium.desktop"),
+        config_directory_name: Cow::Borrowed("VSCodium"),
+        launch_template: GIO_LAUNCH_TEMPLATE,
+        restricted_options: &["--disable-extensions"],
+        fallback_icon: "vscodium",
+        flatpak_desktop_ids: &["com.vscodium.codium.desktop"],
+    },
+    // The official install packages from https://code.visualstudio.com/download
+    CodeVariant {
+        desktop_id: Cow::Borrowed("code.desktop"),
+        config_directory_name: Cow::Borrowed("Code"),
+        launch_template: GIO_LAUNCH_TEMPLATE,
+        restricted_options: &["--disable-extensions"],
+        fallback_icon: "vscode",
+        flatpak_desktop_ids: &["com.visualstudio.code.desktop"],
+    },
+];
+
+/// Derive an app ID from the name of a configuration directory.
+///
+/// Lowercase all ASCII alphanumeric characters, and join all runs of them with
+/// `-`, e.g. `Code - OSS` becomes `code-oss`.
+fn app_id_from_directory_name(name: &str) -> String {
+    name.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Whether the database at `db_path` has recent workspaces under `storage_key`.
+///
+/// See [`CodeVariant::storage_key`].
+fn has_recent_workspaces(db_path: &Path, storage_key: &str) -> bool {
+    open_connection(db_path)
+        .and_then(|connection| query_recently_opened_path_lists(&connection, storage_key))
+        .is_ok_and(|list| list.is_some())
+}
+
+/// Discover unknown variants of Visual Studio Code in `config_dir`, i.e. `$XDG_CONFIG_HOME`.
+///
+/// Look for all configuration directories with a `User/globalStorage/state.vscdb`
+/// database with recent workspaces of Visual Studio Code, and derive a variant
+/// for each, with the desktop ID derived from the directory name, see
+/// [`app_id_from_directory_name`].  Skip all directories and app IDs of `known`
+/// variants.
+///
+/// Each discovered variant still needs a search provider definition for GNOME
+/// Shell to pick it up.
+fn discover_variants(config_dir: &Path, known: &[CodeVariant]) -> Vec<CodeVariant> {
+    let entries = match std::fs::read_dir(config_dir) {
+        Ok(entries) => entries,
+        Err(error) => {
+            glib::warn!(
+                "Failed to discover variants in {}: {error}",
+                config_dir.display()
+            );
+            return Vec::new();
+        }
+    };
+    let mut variants = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| {
+            !known
+                .iter()
+                .any(|variant| variant.config_directory_name == name.as_str())
+        })
+        .filter_map(|name| {
+            let app_id = app_id_from_directory_name(&name);
+            let db_path = config_dir
+                .join(&name)
+                .join("User")
+                .join("globalStorage")
+                .join("state.vscdb");
+            if app_id.is_empty()
+                || known.iter().any(|variant| variant.app_id() == app_id)
+                || !db_path.is_file()
+            {
+                return None;
+            }
+            let variant = CodeVariant {
+                desktop_id: Cow::Owned(format!("{app_id}.desktop")),
+                config_directory_name: Cow::Owned(name),
+                launch_template: GIO_LAUNCH_TEMPLATE,
+                restricted_options: &["--disable-extensions"],
+                fallback_icon: "text-editor",
+                flatpak_desktop_ids: &[],
+            };
+            // Forks may store their recent workspaces under a different key.
+            if has_recent_workspaces(&db_path, &variant.storage_key()) {
+                glib::info!(
+                    "Discovered variant {app_id} in {}",
+                    variant.config_directory_name
+                );
+                Some(variant)
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    variants.sort_by(|a, b| a.desktop_id.cmp(&b.desktop_id));
+    variants
+}
+
+/// Parse a list of app IDs of variants.
+///
+/// Separate app IDs by commas or colons, and ignore whitespace around each app
+/// ID.  Fail if any app ID is empty, e.g. for `code,,codium` or a trailing
+/// separator.
+fn parse_variant_list(value: &str) -> Result<Vec<&str>, glib::Error> {
+    value
+        .split([',', ':'])
+        .map(str::trim)
+        .map(|app_id| {
+            if app_id.is_empty() {
+                Err(glib::Error::new(
+                    IOErrorEnum::InvalidArgument,
+                    "List of variants contains an empty entry",
+                ))
+            } else {
+                Ok(app_id)
+            }
+        })
+        .collect()
+}
+
+/// Get all variants to serve search providers for.
+///
+/// Return all known variants, see [`VARIANTS`], and all discovered variants if
+/// `$VSCODE_SEARCH_PROVIDER_DISCOVER` is `1`, see [`discover_variants`].
+///
+/// If `$VSCODE_SEARCH_PROVIDER_VARIANTS` is set, only return variants whose
+/// app ID it lists, see [`parse_variant_list`].  Ignore the variable if it is
+/// invalid, and warn about listed app IDs which match no variant.
+fn all_variants() -> Vec<CodeVariant> {
+    let mut variants = VARIANTS.to_vec();
+    if environment_flag("VSCODE_SEARCH_PROVIDER_DISCOVER") {
+        let discovered = discover_variants(&glib::user_config_dir(), &variants);
+        variants.extend(discovered);
+    }
+    if let Ok(value) = std::env::var("VSCODE_SEARCH_PROVIDER_VARIANTS") {
+        match parse_variant_list(&value) {
+            Ok(app_ids) => {
+                for app_id in &app_ids {
+                    if !variants.iter().any(|variant| variant.app_id() == *app_id) {
+                        glib::warn!(
+                            "Variant {app_id} in $VSCODE_SEARCH_PROVIDER_VARIANTS not found"
+                        );
+                    }
+                }
+                variants.retain(|variant| app_ids.contains(&variant.app_id()));
+            }
+            Err(error) => {
+                glib::warn!("Ignoring invalid $VSCODE_SEARCH_PROVIDER_VARIANTS {value}: {error}");
+            }
+        }
+    }
+    variants
+}
+
+/// Serve a search provider for `variant` on `connection`.
+///
+/// Skip variants with invalid launch templates, variants which are not
+/// installed, and variants whose workspaces fail to load.
+fn serve_variant(
+    app: &gio::Application,
+    connection: &gio::DBusConnection,
+    interfaces: &ProviderInterfaces,
+    config: ProviderConfig,
+    variant: &CodeVariant,
+) -> Option<RegisteredProvider> {
+    let desktop_id = variant.desktop_id.as_ref();
+    let launch_template = variant.configured_launch_template();
+    if let Err(error) = validate_launch_template(launch_template) {
+        glib::error!("Skipping {desktop_id}, invalid launch template: {error}");
+        return None;
+    }
+    if !variant.launch_options().is_empty() && !launch_template.contains(&LaunchArg::Options) {
+        glib::warn!(
+            "Launch template of {desktop_id} does not support options, ignoring restricted mode and profile"
+        );
+    }
+    let vscode_app = find_desktop_app_info(variant)?;
+    let object_path = format!("{}/{}", app.dbus_object_path().unwrap(), variant.app_id());
+    let db_paths = variant.database_paths();
+    let db_path = &db_paths[0];
+    glib::info!(
+        "Found app {desktop_id}, loading workspaces from db at {} and {} profile databases",
+        db_path.display(),
+        db_paths.len() - 1
+    );
+    let stamps = file_stamps(&db_paths);
+    let (workspaces, load_error) = match load_cached_workspaces_of_variant(
+        variant,
+        &db_paths,
+        &stamps,
+        config.cache,
+    ) {
+        Ok(workspaces) => {
+            glib::info!(
+                "Found {} workspaces for {desktop_id}, exposing search provider at {object_path}",
+                workspaces.len()
+            );
+            (workspaces, None)
+        }
+        Err(error) if config.error_result => {
+            glib::warn!(
+                "Failed to load workspaces from {}, exposing search provider for {desktop_id} at {object_path} with error result: {error}",
+                db_path.display()
+            );
+            (Vec::new(), Some(error.to_string()))
+        }
+        Err(error) => {
+            glib::error!(
+                "Skipping {desktop_id}, failed to load workspaces from {}: {error}",
+                db_path.display()
+            );
+            return None;
+        }
+    };
+    let loaded = load_error.is_none();
+    let provider = SearchProvider::new(
+        app.clone(),
+        variant.clone(),
+        vscode_app,
+        object_path.clone(),
+        Workspaces::new(workspaces, stamps, config.workspace_folder_names),
+        load_error,
+        config,
+    );
+    if loaded {
+        provider.spawn_workspaces_command();
+    }
+    match provider.register(
+        connection,
+        &object_path,
+        &interfaces.search_provider,
+        &interfaces.variant,
+    ) {
+        Ok(registration_ids) => Some(RegisteredProvider {
+            provider,
+            registration_ids,
+        }),
+        Err(error) => {
+            glib::error!("Skipping {desktop_id}, failed to register on {object_path}, {error}");
+            None
+        }
+    }
+}
+
+/// Log that we serve `n_served` of `n_variants` variants.
+///
+/// Warn if we serve none, but keep running nonetheless, for the manager to
+/// rescan variants later.
+fn log_served_variants(n_served: usize, n_variants: usize) {
+    if n_served == 0 {
+        glib::warn!("Not serving any of {n_variants} variants, see previous messages");
+    } else {
+        glib::info!("Serving {n_served} of {n_variants} variants");
+    }
+}
+
+fn startup(app: &gio::Application) {
+    // Hold on to the application during startup, to avoid early exit.
+    let _guard = app.hold();
+
+    let interface = DBusNodeInfo::for_xml(SEARCH_PROVIDER2_XML)
+        .unwrap()
+        .lookup_interface("org.gnome.Shell.SearchProvider2")
+        .unwrap();
+    let vscode_search_provider_node = DBusNodeInfo::for_xml(VSCODE_SEARCH_PROVIDER_XML).unwrap();
+    let variant_interface = vscode_search_provider_node
+        .lookup_interface("de.swsnr.VSCodeSearchProvider.Variant")
+        .unwrap();
+    let manager_interface = vscode_search_provider_node
+        .lookup_interface("de.swsnr.VSCodeSearchProvider.Manager")
+        .unwrap();
+
+    let config = ProviderConfig::from_environment();
+    if config.launch.dry_run {
+        glib::warn!("Dry-run mode enabled, not launching any apps");
+    }
+    if config.launch.no_scope {
+        glib::warn!(
+            "Scopes disabled, launching apps directly; stopping this service may stop all apps it launched, and the Running property cannot tell whether apps run"
+        );
+    }
     let connection = app.dbus_connection().unwrap();
-    for (desktop_id, config_dir_name) in providers {
-        if let Some(vscode_app) = DesktopAppInfo::new(desktop_id) {
-            let object_path = format!(
-                "{}/{}",
-                app.dbus_object_path().unwrap(),
-                vscode_app.id().unwrap().trim_end_matches(".desktop")
+    let interfaces = ProviderInterfaces {
+        search_provider: interface,
+        variant: variant_interface,
+    };
+    // Serve every variant on its own, so that a single variant which fails to
+    // load or register does not take down search providers of all others.
+    let variants = all_variants();
+    let providers = variants
+        .iter()
+        .filter_map(|variant| serve_variant(app, &connection, &interfaces, config, variant))
+        .collect::<Vec<_>>();
+    log_served_variants(providers.len(), variants.len());
+
+    let object_path = app.dbus_object_path().unwrap();
+    let manager = Manager::new(app.clone(), interfaces, config, providers);
+    if let Err(error) = manager.register(&connection, &object_path, &manager_interface) {
+        glib::warn!("Failed to register manager on {object_path}: {error}");
+    }
+}
+
+/// Whether `name` has an owner on the bus of `connection`.
+fn has_owner(connection: &gio::DBusConnection, name: &str) -> Result<bool, glib::Error> {
+    let reply = connection.call_sync(
+        Some("org.freedesktop.DBus"),
+        "/org/freedesktop/DBus",
+        "org.freedesktop.DBus",
+        "NameHasOwner",
+        Some(&(name,).to_variant()),
+        Some(glib::VariantTy::new("(b)").unwrap()),
+        gio::DBusCallFlags::NONE,
+        -1,
+        gio::Cancellable::NONE,
+    )?;
+    Ok(reply.child_value(0).get::<bool>().unwrap())
+}
+
+/// How many times to retry connecting to the session bus at most.
+const SESSION_BUS_MAX_RETRIES: u32 = 6;
+
+/// The delay before the first retry to connect to the session bus.
+const SESSION_BUS_INITIAL_DELAY: Duration = Duration::from_millis(100);
+
+/// The maximum delay between two attempts to connect to the session bus.
+const SESSION_BUS_MAX_DELAY: Duration = Duration::from_secs(2);
+
+/// The delays before each retry to connect to the session bus.
+///
+/// Double the delay after each retry, starting at [`SESSION_BUS_INITIAL_DELAY`],
+/// up to [`SESSION_BUS_MAX_DELAY`], for at most [`SESSION_BUS_MAX_RETRIES`] times,
+/// i.e. for about five seconds in total.
+fn session_bus_backoff_delays() -> impl Iterator<Item = Duration> {
+    (0..SESSION_BUS_MAX_RETRIES)
+        .map(|retry| (SESSION_BUS_INITIAL_DELAY * 2_u32.pow(retry)).min(SESSION_BUS_MAX_DELAY))
+}
+
+/// Connect to the session bus.
+///
+/// During login the session bus may not be available yet when systemd starts us;
+/// retry with a bounded exponential backoff to tolerate this, see
+/// [`session_bus_backoff_delays`].
+fn session_bus_with_retry() -> Result<gio::DBusConnection, glib::Error> {
+    let mut delays = session_bus_backoff_delays();
+    loop {
+        match gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE) {
+            Ok(connection) => return Ok(connection),
+            Err(error) => match delays.next() {
+                Some(delay) => {
+                    glib::warn!(
+                        "Failed to connect to session bus, retrying in {}ms: {error}",
+                        delay.as_millis()
+                    );
+                    std::thread::sleep(delay);
+                }
+                None => return Err(error),
+            },
+        }
+    }
+}
+
+/// Whether another instance of this service already owns our bus name.
+///
+/// If we fail to tell, assume there is none, and let application registration
+/// fail if need be.
+fn is_already_running() -> bool {
+    match session_bus_with_retry().and_then(|connection| has_owner(&connection, APP_ID)) {
+        Ok(owned) => owned,
+        Err(error) => {
+            glib::warn!("Failed to check whether {APP_ID} already has an owner: {error}");
+            false
+        }
+    }
+}
+
+pub fn main() -> glib::ExitCode {
+    static LOGGER: glib::GlibLogger = glib::GlibLogger::new(
+        glib::GlibLoggerFormat::Structured,
+        glib::GlibLoggerDomain::CrateTarget,
+    );
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    // Registering our application fails if another instance already serves
+    // search providers; bow out gracefully instead of failing, which would make
+    // systemd restart us over and over again.
+    if is_already_running() {
+        glib::info!("Another instance already owns {APP_ID}, exiting");
+        return glib::ExitCode::SUCCESS;
+    }
+
+    let app = gio::Application::builder()
+        .application_id(APP_ID)
+        .flags(ApplicationFlags::IS_SERVICE)
+        // Exit one minute after release the app, i.e. in our case after finishing
+        // the last DBus call.
+        .inactivity_timeout(Duration::from_secs(60).as_millis().try_into().unwrap())
+        .build();
+
+    app.set_version(env!("CARGO_PKG_VERSION"));
+    app.connect_startup(startup);
+    app.run()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desktop_app_info(extra_keys: &str) -> DesktopAppInfo {
+        let keyfile = glib::KeyFile::new();
+        keyfile
+            .load_from_data(
+                &format!("[Desktop Entry]\nType=Application\nName=Code\nExec=sh\n{extra_keys}"),
+                glib::KeyFileFlags::NONE,
+            )
+            .unwrap();
+        DesktopAppInfo::from_keyfile(&keyfile).unwrap()
+    }
+
+    #[test]
+    fn dbus_activation_requires_dbus_activatable_entry() {
+        let enabled = LaunchConfig {
+            dbus_activation: true,
+            ..LaunchConfig::default()
+        };
+        let activatable = desktop_app_info("DBusActivatable=true\n");
+        let not_activatable = desktop_app_info("");
+        assert!(uses_dbus_activation(enabled, &activatable));
+        assert!(!uses_dbus_activation(enabled, &not_activatable));
+        assert!(!uses_dbus_activation(LaunchConfig::default(), &activatable));
+    }
+
+    #[test]
+    fn application_activation_call_matches_interface() {
+        let (method, parameters) = application_activation_call(Some("file:///home/me/project"));
+        assert_eq!(method, "Open");
+        assert_eq!(parameters.type_().as_str(), "(asa{sv})");
+        let (method, parameters) = application_activation_call(None);
+        assert_eq!(method, "Activate");
+        assert_eq!(parameters.type_().as_str(), "(a{sv})");
+    }
+
+    #[test]
+    fn workspace_name_ignores_trailing_slash() {
+        let config = DisplayConfig::default();
+        assert_eq!(
+            workspace_name(config, "file:///home/me/project/"),
+            "project"
+        );
+    }
+
+    #[test]
+    fn workspace_name_ignores_fragment_and_query() {
+        let config = DisplayConfig::default();
+        assert_eq!(
+            workspace_name(
+                config,
+                "vscode-remote://ssh-remote+host/home/me/project#main"
+            ),
+            "project"
+        );
+        assert_eq!(
+            workspace_name(
+                config,
+                "vscode-remote://ssh-remote+host/home/me/project?ref=1"
+            ),
+            "project"
+        );
+        assert_eq!(workspace_name(config, "no uri/project?ref=1"), "project");
+    }
+
+    #[test]
+    fn workspace_name_keeps_encoded_hash() {
+        let config = DisplayConfig::default();
+        assert_eq!(
+            workspace_name(config, "file:///home/me/src/F%23-tools"),
+            "F#-tools"
+        );
+    }
+
+    #[test]
+    fn matching_file_keeps_encoded_hash() {
+        let patterns = vec!["*-tools.txt".to_owned()];
+        assert!(is_matching_file(
+            &patterns,
+            "file:///home/me/src/F%23-tools.txt"
+        ));
+    }
+
+    /// Create a new empty directory for `test` under the temporary directory.
+    fn test_directory(test: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "gnome-search-providers-vscode-{}-{test}",
+            std::process::id()
+        ));
+        if directory.exists() {
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn write_desktop_entry(directory: &Path, desktop_id: &str, name: &str) {
+        std::fs::write(
+            directory.join(desktop_id),
+            format!("[Desktop Entry]\nType=Application\nName={name}\nExec=sh\n"),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn flatpak_desktop_entries_search_directories_in_order() {
+        let directory = test_directory("flatpak-search-order");
+        let user = directory.join("user");
+        let system = directory.join("system");
+        std::fs::create_dir_all(&user).unwrap();
+        std::fs::create_dir_all(&system).unwrap();
+        write_desktop_entry(&user, "com.vscodium.codium.desktop", "User Codium");
+        write_desktop_entry(&system, "com.vscodium.codium.desktop", "System Codium");
+        write_desktop_entry(&system, "com.visualstudio.code.desktop", "System Code");
+        let directories = [user, system];
+
+        let codium = find_flatpak_desktop_app_info(&["com.vscodium.codium.desktop"], &directories);
+        assert_eq!(codium.unwrap().name(), "User Codium");
+        let code = find_flatpak_desktop_app_info(&["com.visualstudio.code.desktop"], &directories);
+        assert_eq!(code.unwrap().name(), "System Code");
+        // Never look up desktop IDs of native entries in export directories.
+        assert!(find_flatpak_desktop_app_info(&["code.desktop"], &directories).is_none());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn known_variants_have_flatpak_desktop_ids() {
+        for variant in &VARIANTS {
+            assert!(!variant.flatpak_desktop_ids.is_empty());
+            assert!(!variant
+                .flatpak_desktop_ids
+                .contains(&variant.desktop_id.as_ref()));
+        }
+    }
+
+    #[test]
+    fn workspaces_decode_canonical_uris() {
+        let workspaces = Workspaces::new(
+            vec![
+                "file:///home/me/project/".to_owned(),
+                "file:///home/me/project".to_owned(),
+                "file:///home/me/%7Eother".to_owned(),
+            ],
+            Vec::new(),
+            false,
+        );
+        assert_eq!(
+            workspaces.decoded(&workspaces.ids).collect::<Vec<_>>(),
+            [
+                ("file:///home/me/project", "/home/me/project"),
+                ("file:///home/me/~other", "/home/me/~other")
+            ]
+        );
+        assert_eq!(
+            workspaces.uri("file:///home/me/project"),
+            "file:///home/me/project/"
+        );
+    }
+
+    #[test]
+    fn application_object_path_follows_specification() {
+        assert_eq!(
+            application_object_path("org.example.My-App"),
+            "/org/example/My_App"
+        );
+    }
+
+    const PLAIN_LIST: &str =
+        r#"{"entries":[{"folderUri":"file:///home/me/a"},{"fileUri":"file:///home/me/b.txt"}]}"#;
+
+    /// Create a database with `value` stored under the default storage key.
+    fn database_with_list(connection: &rusqlite::Connection, value: &serde_json::Value) {
+        database_with_list_under(connection, RECENTLY_OPENED_PATHS_LIST_KEY, value);
+    }
+
+    /// Create a database with `value` stored under `storage_key`.
+    fn database_with_list_under(
+        connection: &rusqlite::Connection,
+        storage_key: &str,
+        value: &serde_json::Value,
+    ) {
+        connection
+            .execute_batch(
+                "CREATE TABLE ItemTable (key TEXT UNIQUE ON CONFLICT REPLACE, value BLOB);",
+            )
+            .unwrap();
+        connection
+            .execute(
+                "INSERT INTO ItemTable (key, value) VALUES (?1, ?2);",
+                rusqlite::params![storage_key, value],
+            )
+            .unwrap();
+    }
+
+    /// Create an in-memory database with `value` stored under the default storage key.
+    fn in_memory_database_with_list(value: &serde_json::Value) -> rusqlite::Connection {
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+        database_with_list(&connection, value);
+        connection
+    }
+
+    /// Options to load all workspaces under the default storage key.
+    fn load_all_options() -> LoadOptions<'static> {
+        LoadOptions {
+            storage_key: RECENTLY_OPENED_PATHS_LIST_KEY,
+            limit: usize::MAX,
+            file_patterns: Vec::new(),
+            excluded_paths: Vec::new(),
+            skip_hidden: false,
+        }
+    }
+
+    /// The list of recently opened paths in all shapes we know.
+    fn stored_list_shapes() -> Vec<serde_json::Value> {
+        let plain = serde_json::from_str::<serde_json::Value>(PLAIN_LIST).unwrap();
+        vec![
+            plain.clone(),
+            serde_json::Value::String(PLAIN_LIST.to_string()),
+            serde_json::json!({"version": 2, "value": plain}),
+            serde_json::json!({"version": 2, "data": plain}),
+        ]
+    }
+
+    #[test]
+    fn query_recently_opened_path_lists_in_all_shapes() {
+        for value in stored_list_shapes() {
+            let connection = in_memory_database_with_list(&value);
+            let list =
+                query_recently_opened_path_lists(&connection, RECENTLY_OPENED_PATHS_LIST_KEY)
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(list.entries.unwrap().len(), 2, "{value}");
+        }
+    }
+
+    #[test]
+    fn query_recently_opened_path_lists_without_list() {
+        let connection = in_memory_database_with_list(&serde_json::json!({}));
+        assert!(
+            query_recently_opened_path_lists(&connection, "some.other.key")
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[cfg(feature = "forget-workspace")]
+    #[test]
+    fn forget_workspace_in_database_keeps_the_shape() {
+        let directory = test_directory("forget-workspace");
+        for (index, value) in stored_list_shapes().into_iter().enumerate() {
+            let db_path = directory.join(format!("state-{index}.vscdb"));
+            database_with_list(&rusqlite::Connection::open(&db_path).unwrap(), &value);
+            assert!(forget_workspace_in_database(
+                &db_path,
+                RECENTLY_OPENED_PATHS_LIST_KEY,
+                "file:///home/me/a/"
+            )
+            .unwrap());
+            assert!(!forget_workspace_in_database(
+                &db_path,
+                RECENTLY_OPENED_PATHS_LIST_KEY,
+                "file:///home/me/a"
+            )
+            .unwrap());
+
+            let connection = rusqlite::Connection::open(&db_path).unwrap();
+            let stored: serde_json::Value = connection
+                .query_row("SELECT value FROM ItemTable;", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(stored.is_string(), value.is_string(), "{value}");
+            assert_eq!(
+                wrapped_list_key(&stored),
+                wrapped_list_key(&value),
+                "{value}"
             );
-            let db_path = user_config_dir
-                .join(config_dir_name)
+            let list =
+                query_recently_opened_path_lists(&connection, RECENTLY_OPENED_PATHS_LIST_KEY)
+                    .unwrap()
+                    .unwrap();
+            let entries = list.entries.unwrap();
+            assert_eq!(entries.len(), 1, "{value}");
+            assert!(matches!(
+                &entries[0],
+                StorageOpenedPathsListEntry::File { uri } if uri == "file:///home/me/b.txt"
+            ));
+        }
+    }
+
+    #[test]
+    fn has_recent_workspaces_under_storage_key() {
+        let db_path = test_directory("recent-workspaces").join("state.vscdb");
+        database_with_list_under(
+            &rusqlite::Connection::open(&db_path).unwrap(),
+            "fork.recentlyOpenedPathsList",
+            &serde_json::from_str(PLAIN_LIST).unwrap(),
+        );
+        assert!(has_recent_workspaces(
+            &db_path,
+            "fork.recentlyOpenedPathsList"
+        ));
+        assert!(!has_recent_workspaces(
+            &db_path,
+            RECENTLY_OPENED_PATHS_LIST_KEY
+        ));
+    }
+
+    /// A list with a folder, a workspace, two untitled workspaces, and two files.
+    fn mixed_list() -> serde_json::Value {
+        serde_json::json!({"entries": [
+            {"folderUri": "file:///home/me/folder"},
+            {"workspace": {"id": "1", "configPath": "file:///home/me/a.code-workspace"}},
+            {"workspace": {
+                "id": "2",
+                "configPath": "file:///home/me/.config/Code/Workspaces/2/workspace.json"
+            }},
+            {"workspace": {
+                "id": "3",
+                "configPath": "file:///home/me/.config/Code/User/workspaceStorage/3/Untitled-3.code-workspace"
+            }},
+            {"fileUri": "file:///home/me/notes.md"},
+            {"fileUri": "file:///home/me/main.rs"},
+        ]})
+    }
+
+    #[test]
+    fn load_workspaces_loads_folders_and_workspaces() {
+        let connection = in_memory_database_with_list(&mixed_list());
+        assert_eq!(
+            load_workspaces(&connection, &load_all_options()).unwrap(),
+            ["file:///home/me/folder", "file:///home/me/a.code-workspace"]
+        );
+    }
+
+    #[test]
+    fn load_workspaces_loads_matching_files() {
+        let connection = in_memory_database_with_list(&mixed_list());
+        let options = LoadOptions {
+            file_patterns: vec!["*.md".to_owned()],
+            ..load_all_options()
+        };
+        assert_eq!(
+            load_workspaces(&connection, &options).unwrap(),
+            [
+                "file:///home/me/folder",
+                "file:///home/me/a.code-workspace",
+                "file:///home/me/notes.md"
+            ]
+        );
+    }
+
+    #[test]
+    fn load_workspaces_keeps_most_recent_workspaces() {
+        let connection = in_memory_database_with_list(&mixed_list());
+        let options = LoadOptions {
+            limit: 1,
+            ..load_all_options()
+        };
+        assert_eq!(
+            load_workspaces(&connection, &options).unwrap(),
+            ["file:///home/me/folder"]
+        );
+    }
+
+    #[test]
+    fn load_workspaces_without_list() {
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+        database_with_list_under(&connection, "some.other.key", &mixed_list());
+        assert!(load_workspaces(&connection, &load_all_options())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn host_and_path_terms_match_remote_workspace_together() {
+        let uris = [
+            "vscode-remote://ssh-remote+devbox/home/me/backend",
+            "vscode-remote://ssh-remote+other/home/me/backend",
+            "file:///home/me/devbox",
+        ];
+        let decoded = uris
+            .iter()
+            .map(|uri| decode_uri_for_matching(uri).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(decoded[0], "devbox/home/me/backend");
+        let entries = uris.iter().copied().zip(decoded.iter().map(String::as_str));
+        assert_eq!(
+            find_matching_uris(
+                ScoringConfig::default(),
+                entries.clone(),
+                &["backend", "devbox"]
+            ),
+            [uris[0]]
+        );
+        // The kind of remote host never matches.
+        assert!(
+            find_matching_uris(ScoringConfig::default(), entries, &["backend", "ssh"]).is_empty()
+        );
+    }
+
+    #[test]
+    fn decode_uri_decodes_local_paths() {
+        assert_eq!(
+            decode_uri("file:///home/me/my%20project").as_deref(),
+            Some("/home/me/my project")
+        );
+        assert_eq!(
+            decode_uri("file:///home/me/c++").as_deref(),
+            Some("/home/me/c++")
+        );
+        assert_eq!(
+            decode_uri("file:///c%3A/Users/me").as_deref(),
+            Some("C:/Users/me")
+        );
+    }
+
+    #[test]
+    fn decode_uri_decodes_remote_uris_as_a_whole() {
+        assert_eq!(
+            decode_uri("vscode-remote://ssh-remote%2Bdevbox/home/me/my%20project").as_deref(),
+            Some("vscode-remote://ssh-remote+devbox/home/me/my project")
+        );
+        assert_eq!(decode_uri("not a uri"), None);
+    }
+
+    #[test]
+    fn name_and_description_of_local_uris() {
+        let config = DisplayConfig::default();
+        assert_eq!(
+            name_and_description_of_uri(config, "file:///home/me/my%20project"),
+            ("my project".to_owned(), "/home/me/my project".to_owned())
+        );
+        assert_eq!(
+            name_and_description_of_uri(config, "file:///home/me/app.code-workspace"),
+            (
+                "app (Workspace)".to_owned(),
+                "/home/me/app.code-workspace".to_owned()
+            )
+        );
+    }
+
+    #[test]
+    fn canonical_uri_unifies_equivalent_uris() {
+        assert_eq!(
+            canonical_uri("file:///home/me/%7Eproject"),
+            canonical_uri("file:///home/me/~project")
+        );
+        assert_eq!(
+            canonical_uri("file:///home/me/project/"),
+            "file:///home/me/project"
+        );
+        assert_eq!(canonical_uri("file:///"), "file:///");
+        assert_eq!(canonical_uri("not a uri"), "not a uri");
+    }
+
+    #[test]
+    fn parse_variant_list_accepts_commas_and_colons() {
+        assert_eq!(
+            parse_variant_list("code,codium").unwrap(),
+            ["code", "codium"]
+        );
+        assert_eq!(
+            parse_variant_list("code:codium").unwrap(),
+            ["code", "codium"]
+        );
+        assert_eq!(
+            parse_variant_list(" code , codium: code-oss ").unwrap(),
+            ["code", "codium", "code-oss"]
+        );
+    }
+
+    #[test]
+    fn parse_variant_list_rejects_empty_entries() {
+        for value in ["", "code,,codium", "code:", " , codium"] {
+            let error = parse_variant_list(value).unwrap_err();
+            assert!(error.matches(IOErrorEnum::InvalidArgument), "{value:?}");
+        }
+    }
+
+    /// Get only the names of `uris`, see [`names_and_descriptions_of_uris`].
+    fn names_of_uris(config: DisplayConfig, uris: &[&str]) -> Vec<String> {
+        names_and_descriptions_of_uris(config, uris)
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    #[test]
+    fn disambiguate_equal_names() {
+        let uris = [
+            "file:///home/me/a/src",
+            "file:///home/me/b/src",
+            "file:///home/me/c/x/app",
+            "file:///home/me/d/x/app",
+            "file:///home/me/tools",
+        ];
+        let config = DisplayConfig {
+            disambiguate_names: true,
+            ..DisplayConfig::default()
+        };
+        assert_eq!(
+            names_of_uris(config, &uris),
+            ["a/src", "b/src", "c/x/app", "d/x/app", "tools"]
+        );
+        assert_eq!(
+            names_of_uris(DisplayConfig::default(), &uris),
+            ["src", "src", "app", "app", "tools"]
+        );
+    }
+
+    #[test]
+    fn disambiguate_identical_names_terminates() {
+        let config = DisplayConfig {
+            disambiguate_names: true,
+            ..DisplayConfig::default()
+        };
+        // Both names have no more segments to tell them apart.
+        assert_eq!(
+            names_of_uris(config, &["file:///src", "file:///src/"]),
+            ["src", "src"]
+        );
+    }
+
+    #[test]
+    fn glob_matches_names() {
+        assert!(glob_matches("*.yml", "docker-compose.yml"));
+        assert!(glob_matches(".env", ".env"));
+        assert!(glob_matches("docker-compose.*", "docker-compose.yaml"));
+        assert!(glob_matches("?.md", "a.md"));
+        assert!(glob_matches("*", ""));
+        assert!(!glob_matches("*.yml", "docker-compose.yaml"));
+        assert!(!glob_matches("?.md", "ab.md"));
+        assert!(!glob_matches(".env", ".envrc"));
+    }
+
+    #[test]
+    fn load_workspaces_loads_only_files_matching_patterns() {
+        let connection = in_memory_database_with_list(&serde_json::json!({"entries": [
+            {"fileUri": "file:///home/me/app/.env"},
+            {"fileUri": "file:///home/me/app/docker-compose.yml"},
+            {"fileUri": "file:///home/me/app/main.rs"},
+            {"fileUri": "file:///home/me/app/.env.yml/README.md"},
+            {"folderUri": "file:///home/me/app"},
+        ]}));
+        let options = LoadOptions {
+            file_patterns: vec![".env".to_owned(), "*.yml".to_owned()],
+            ..load_all_options()
+        };
+        assert_eq!(
+            load_workspaces(&connection, &options).unwrap(),
+            [
+                "file:///home/me/app/.env",
+                "file:///home/me/app/docker-compose.yml",
+                "file:///home/me/app"
+            ]
+        );
+    }
+
+    /// Write an executable shell script with `body` to `directory`.
+    fn write_script(directory: &Path, name: &str, body: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let path = directory.join(name);
+        std::fs::write(&path, format!("#!/bin/sh\n{body}\n")).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    /// Run `future` to completion on a new thread-default main context.
+    fn run_local<F: std::future::Future>(future: F) -> F::Output {
+        let context = glib::MainContext::new();
+        context
+            .with_thread_default(|| context.block_on(future))
+            .unwrap()
+    }
+
+    /// A variant with the given `app_id`, to configure in tests.
+    fn test_variant(app_id: &str) -> CodeVariant {
+        CodeVariant {
+            desktop_id: Cow::Owned(format!("{app_id}.desktop")),
+            config_directory_name: Cow::Owned(app_id.to_owned()),
+            launch_template: GIO_LAUNCH_TEMPLATE,
+            restricted_options: &["--disable-extensions"],
+            fallback_icon: "text-editor",
+            flatpak_desktop_ids: &[],
+        }
+    }
+
+    /// A provider for `variant` with `workspaces`, on an unregistered application.
+    fn test_provider(variant: CodeVariant, workspaces: Workspaces) -> Rc<SearchProvider> {
+        configured_test_provider(variant, workspaces, None, ProviderConfig::default())
+    }
+
+    /// Like [`test_provider`], but with a `load_error` and a `config`.
+    fn configured_test_provider(
+        variant: CodeVariant,
+        workspaces: Workspaces,
+        load_error: Option<String>,
+        config: ProviderConfig,
+    ) -> Rc<SearchProvider> {
+        SearchProvider::new(
+            Application::new(None, ApplicationFlags::empty()),
+            variant,
+            desktop_app_info(""),
+            "/de/swsnr/test".to_owned(),
+            workspaces,
+            load_error,
+            config,
+        )
+    }
+
+    #[test]
+    fn load_workspaces_from_command_returns_non_empty_lines() {
+        let directory = test_directory("command-success");
+        let command = write_script(
+            &directory,
+            "workspaces",
+            "echo file:///home/me/a\necho\necho '  file:///home/me/b  '",
+        );
+        assert_eq!(
+            run_local(load_workspaces_from_command(
+                &command,
+                WORKSPACES_COMMAND_TIMEOUT
+            ))
+            .unwrap(),
+            ["file:///home/me/a", "file:///home/me/b"]
+        );
+    }
+
+    #[test]
+    fn load_workspaces_from_command_fails_on_non_zero_exit() {
+        let directory = test_directory("command-failure");
+        let command = write_script(&directory, "workspaces", "echo file:///home/me/a\nexit 3");
+        let error = run_local(load_workspaces_from_command(
+            &command,
+            WORKSPACES_COMMAND_TIMEOUT,
+        ))
+        .unwrap_err();
+        assert!(error.matches(IOErrorEnum::Failed), "{error}");
+    }
+
+    #[test]
+    fn load_workspaces_from_command_times_out() {
+        let directory = test_directory("command-timeout");
+        let command = write_script(&directory, "workspaces", "exec sleep 10");
+        let start = std::time::Instant::now();
+        let error = run_local(load_workspaces_from_command(
+            &command,
+            Duration::from_millis(100),
+        ))
+        .unwrap_err();
+        assert!(error.matches(IOErrorEnum::TimedOut), "{error}");
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    /// Set up a provider with workspaces from a database, and `command_body` as
+    /// workspaces command, and run the command.
+    fn provider_with_workspaces_command(test: &str, command_body: &str) -> Rc<SearchProvider> {
+        let directory = test_directory(test);
+        let variant = test_variant(&format!("de.swsnr.test.{test}"));
+        let db_path = directory.join("state.vscdb");
+        database_with_list(
+            &rusqlite::Connection::open(&db_path).unwrap(),
+            &serde_json::from_str(PLAIN_LIST).unwrap(),
+        );
+        let command = write_script(&directory, "workspaces", command_body);
+        std::env::set_var(variant.environment_variable("DB"), &db_path);
+        std::env::set_var(variant.environment_variable("WORKSPACES_COMMAND"), &command);
+        let db_paths = variant.database_paths();
+        let stamps = file_stamps(&db_paths);
+        let uris = load_cached_workspaces_of_variant(&variant, &db_paths, &stamps, false).unwrap();
+        let provider = test_provider(variant, Workspaces::new(uris, stamps, false));
+        run_local(async { provider.spawn_workspaces_command().unwrap().await }).unwrap();
+        provider
+    }
+
+    #[test]
+    fn workspaces_command_adds_workspaces_after_databases() {
+        let provider = provider_with_workspaces_command(
+            "command-adds",
+            "echo file:///home/me/c\necho file:///home/me/a",
+        );
+        assert_eq!(
+            provider.workspaces.borrow().ids,
+            ["file:///home/me/a", "file:///home/me/c"]
+        );
+    }
+
+    #[test]
+    fn failing_workspaces_command_keeps_workspaces_from_databases() {
+        let provider = provider_with_workspaces_command("command-fallback", "exit 1");
+        assert_eq!(provider.workspaces.borrow().ids, ["file:///home/me/a"]);
+    }
+
+    #[test]
+    fn command_workspaces_are_discarded_after_reload() {
+        let provider = test_provider(
+            test_variant("de.swsnr.test.command-discard"),
+            Workspaces::new(vec!["file:///home/me/a".to_owned()], vec![None], false),
+        );
+        let outdated = [Some(FileStamp {
+            modified: SystemTime::UNIX_EPOCH,
+            device: 0,
+            inode: 0,
+        })];
+        assert!(!provider.add_command_workspaces(&outdated, vec!["file:///home/me/c".to_owned()]));
+        assert!(provider.add_command_workspaces(&[None], vec!["file:///home/me/c".to_owned()]));
+        assert_eq!(
+            provider.workspaces.borrow().ids,
+            ["file:///home/me/a", "file:///home/me/c"]
+        );
+    }
+
+    #[test]
+    fn scope_unit_names_are_unique() {
+        let names = (0..10_000)
+            .map(|_| scope_unit_name("com.visualstudio.code"))
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(names.len(), 10_000);
+    }
+
+    #[test]
+    fn scope_unit_names_follow_application_scope_convention() {
+        let name = scope_unit_name("com.visualstudio.code-oss");
+        let suffix = name
+            .strip_prefix("app-gnome-com.visualstudio.code\\x2doss-")
+            .and_then(|name| name.strip_suffix(".scope"))
+            .unwrap();
+        assert_eq!(suffix.len(), 16);
+        assert!(suffix.chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn validate_launch_template_requires_program_and_uri() {
+        assert!(validate_launch_template(GIO_LAUNCH_TEMPLATE).is_ok());
+        assert!(validate_launch_template(CLI_LAUNCH_TEMPLATE).is_ok());
+        for template in [
+            &[][..],
+            &[LaunchArg::Uri],
+            &[LaunchArg::Options, LaunchArg::Command, LaunchArg::Uri],
+            &[LaunchArg::Literal("/usr/bin/code")],
+            &[LaunchArg::Command, LaunchArg::Options],
+            &[LaunchArg::Command, LaunchArg::Uri, LaunchArg::OpenUri],
+        ] {
+            let error = validate_launch_template(template).unwrap_err();
+            assert!(error.matches(IOErrorEnum::InvalidArgument), "{template:?}");
+        }
+    }
+
+    /// Expand `template` for a folder URI, or without any URI.
+    fn expand_test_template(template: &[LaunchArg], uri: Option<&str>) -> Vec<String> {
+        let command = vec![
+            OsString::from("/usr/bin/code"),
+            OsString::from("--new-window"),
+        ];
+        let options = vec!["--disable-extensions".to_owned()];
+        expand_launch_template(
+            template,
+            &LaunchTemplateArgs {
+                desktop_file: Path::new("/usr/share/applications/code.desktop"),
+                command: &command,
+                options: &options,
+                uri,
+            },
+        )
+        .into_iter()
+        .map(|arg| arg.to_str().unwrap().to_owned())
+        .collect()
+    }
+
+    #[test]
+    fn expand_gio_launch_template() {
+        assert_eq!(
+            expand_test_template(GIO_LAUNCH_TEMPLATE, Some("vscode-remote://ssh-remote+a/b")),
+            [
+                "/usr/bin/gio",
+                "launch",
+                "/usr/share/applications/code.desktop",
+                "vscode-remote://ssh-remote+a/b"
+            ]
+        );
+        assert_eq!(
+            expand_test_template(GIO_LAUNCH_TEMPLATE, None),
+            [
+                "/usr/bin/gio",
+                "launch",
+                "/usr/share/applications/code.desktop"
+            ]
+        );
+    }
+
+    #[test]
+    fn expand_cli_launch_template() {
+        assert_eq!(
+            expand_test_template(CLI_LAUNCH_TEMPLATE, Some("vscode-remote://ssh-remote+a/b")),
+            [
+                "/usr/bin/code",
+                "--new-window",
+                "--disable-extensions",
+                "--folder-uri",
+                "vscode-remote://ssh-remote+a/b"
+            ]
+        );
+        assert_eq!(
+            expand_test_template(CLI_LAUNCH_TEMPLATE, None),
+            ["/usr/bin/code", "--new-window", "--disable-extensions"]
+        );
+    }
+
+    #[test]
+    fn workspace_count_changes_only_after_reload() {
+        let directory = test_directory("workspace-count");
+        let variant = test_variant("de.swsnr.test.workspace-count");
+        let db_path = directory.join("state.vscdb");
+        database_with_list(
+            &rusqlite::Connection::open(&db_path).unwrap(),
+            &serde_json::from_str(PLAIN_LIST).unwrap(),
+        );
+        std::env::set_var(variant.environment_variable("DB"), &db_path);
+        let db_paths = variant.database_paths();
+        let stamps = file_stamps(&db_paths);
+        let uris = load_cached_workspaces_of_variant(&variant, &db_paths, &stamps, false).unwrap();
+        let provider = test_provider(variant, Workspaces::new(uris, stamps, false));
+        assert_eq!(provider.workspace_count(), 1);
+
+        // Replace the database, as Visual Studio Code does.
+        let new_db_path = directory.join("state.vscdb.new");
+        database_with_list(
+            &rusqlite::Connection::open(&new_db_path).unwrap(),
+            &mixed_list(),
+        );
+        std::fs::rename(&new_db_path, &db_path).unwrap();
+        assert_eq!(provider.workspace_count(), 1);
+        provider.refresh_workspaces();
+        assert_eq!(provider.workspace_count(), 2);
+    }
+
+    #[test]
+    fn search_provider_serves_its_interfaces_on_a_test_bus() {
+        let bus = gio::TestDBus::new(gio::TestDBusFlags::NONE);
+        bus.up();
+        let address = bus.bus_address().unwrap();
+        let search_provider_interface = DBusNodeInfo::for_xml(SEARCH_PROVIDER2_XML)
+            .unwrap()
+            .lookup_interface("org.gnome.Shell.SearchProvider2")
+            .unwrap();
+        let variant_interface = DBusNodeInfo::for_xml(VSCODE_SEARCH_PROVIDER_XML)
+            .unwrap()
+            .lookup_interface("de.swsnr.VSCodeSearchProvider.Variant")
+            .unwrap();
+        let uris = vec![
+            "file:///home/me/acme".to_owned(),
+            "file:///home/me/b".to_owned(),
+        ];
+        let provider = test_provider(
+            test_variant("de.swsnr.test.bus"),
+            Workspaces::new(uris, vec![None], false),
+        );
+        let (initial_results, metas) = run_local(async {
+            let connection = gio::DBusConnection::for_address_future(
+                &address,
+                gio::DBusConnectionFlags::AUTHENTICATION_CLIENT
+                    | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+                None,
+            )
+            .await
+            .unwrap();
+            let ids = provider
+                .register(
+                    &connection,
+                    "/de/swsnr/test",
+                    &search_provider_interface,
+                    &variant_interface,
+                )
+                .unwrap();
+            let call = |interface: &str, method: &str, args: Variant, reply: &str| {
+                connection.call_future(
+                    connection.unique_name().as_deref(),
+                    "/de/swsnr/test",
+                    interface,
+                    method,
+                    Some(&args),
+                    Some(glib::VariantTy::new(reply).unwrap()),
+                    gio::DBusCallFlags::NONE,
+                    5000,
+                )
+            };
+            let initial_results = call(
+                "org.gnome.Shell.SearchProvider2",
+                "GetInitialResultSet",
+                (vec!["acme"],).to_variant(),
+                "(as)",
+            )
+            .await
+            .unwrap();
+            let metas = call(
+                "org.gnome.Shell.SearchProvider2",
+                "GetResultMetas",
+                (vec!["file:///home/me/acme"],).to_variant(),
+                "(aa{sv})",
+            )
+            .await
+            .unwrap();
+            for id in ids {
+                connection.unregister_object(id).unwrap();
+            }
+            connection.close_future().await.unwrap();
+            (initial_results, metas)
+        });
+        bus.down();
+        assert_eq!(
+            initial_results.get::<(Vec<String>,)>().unwrap().0,
+            ["file:///home/me/acme"]
+        );
+        assert_eq!(metas.n_children(), 1);
+        assert_eq!(metas.child_value(0).n_children(), 1);
+    }
+
+    #[test]
+    fn icons_serialize_to_icon_serialization_signature() {
+        let signature = glib::VariantTy::new("(sv)").unwrap();
+        let themed = gio::ThemedIcon::new("code").serialize().unwrap();
+        assert_eq!(themed.type_(), signature);
+        let file = gio::FileIcon::new(&gio::File::for_path("/usr/share/pixmaps/code.png"))
+            .serialize()
+            .unwrap();
+        assert_eq!(file.type_(), signature);
+        let provider = test_provider(
+            test_variant("de.swsnr.test.icon"),
+            Workspaces::new(Vec::new(), vec![None], false),
+        );
+        assert_eq!(provider.app_icon().unwrap().type_(), signature);
+    }
+
+    #[test]
+    fn session_bus_backoff_doubles_delays_up_to_maximum() {
+        let delays = session_bus_backoff_delays()
+            .map(|delay| delay.as_millis())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [100, 200, 400, 800, 1600, 2000]);
+        assert_eq!(delays.len(), SESSION_BUS_MAX_RETRIES as usize);
+    }
+
+    #[test]
+    fn truncate_end_keeps_short_text_and_ends_with_ellipsis() {
+        assert_eq!(truncate_end("project", 10), "project");
+        assert_eq!(truncate_end("project", 7), "project");
+        assert_eq!(truncate_end("project", 5), "proj…");
+        assert_eq!(truncate_end("épée-café", 9), "épée-café");
+        assert_eq!(truncate_end("épée-café", 5), "épée…");
+    }
+
+    #[test]
+    fn truncate_middle_keeps_start_and_end() {
+        assert_eq!(truncate_middle("/home/me", 10), "/home/me");
+        assert_eq!(truncate_middle("/home/me", 8), "/home/me");
+        assert_eq!(truncate_middle("/home/me/project", 8), "/ho…ject");
+        assert_eq!(truncate_middle("/home/me/project", 7), "/ho…ect");
+        assert_eq!(truncate_middle("/é/é/é/é/é", 5), "/é…/é");
+    }
+
+    #[test]
+    fn is_excluded_matches_whole_path_segments() {
+        let excluded = [PathBuf::from("/a/b")];
+        assert!(is_excluded(&excluded, "file:///a/b"));
+        assert!(is_excluded(&excluded, "file:///a/b/"));
+        assert!(is_excluded(&excluded, "file:///a/b/c"));
+        assert!(!is_excluded(&excluded, "file:///a/bc"));
+        assert!(!is_excluded(&excluded, "file:///a"));
+        assert!(!is_excluded(
+            &excluded,
+            "vscode-remote://ssh-remote+host/a/b"
+        ));
+        assert!(!is_excluded(&[], "file:///a/b"));
+    }
+
+    #[test]
+    fn is_hidden_checks_all_path_segments() {
+        assert!(is_hidden("file:///home/me/.config/nvim"));
+        assert!(is_hidden("file:///home/me/.dotfiles"));
+        assert!(is_hidden("file:///home/me/src/.env.code-workspace"));
+        assert!(!is_hidden("file:///home/me/src/project"));
+        assert!(!is_hidden("file:///home/me/src/project.v2"));
+        assert!(!is_hidden(
+            "vscode-remote://ssh-remote+host/home/me/.config"
+        ));
+    }
+
+    #[test]
+    fn containing_folder_of_local_workspaces() {
+        assert_eq!(
+            containing_folder("file:///home/me/app.code-workspace").unwrap(),
+            "file:///home/me"
+        );
+        assert_eq!(
+            containing_folder("file:///home/me/project/").unwrap(),
+            "file:///home/me"
+        );
+        let root = containing_folder("file:///").unwrap_err();
+        assert!(root.matches(IOErrorEnum::NotFound), "{root}");
+        let remote = containing_folder("vscode-remote://ssh-remote+host/home/me").unwrap_err();
+        assert!(remote.matches(IOErrorEnum::NotSupported), "{remote}");
+    }
+
+    #[test]
+    fn open_uri_option_opens_files_with_file_uri() {
+        let directory = test_directory("open-uri-option");
+        let file = directory.join("notes.md");
+        std::fs::write(&file, "").unwrap();
+        let uri_of = |path: &Path| gio::File::for_path(path).uri().to_string();
+        assert_eq!(open_uri_option(&uri_of(&directory)), "--folder-uri");
+        assert_eq!(open_uri_option(&uri_of(&file)), "--file-uri");
+        assert_eq!(
+            open_uri_option(&uri_of(&directory.join("app.code-workspace"))),
+            "--file-uri"
+        );
+        assert_eq!(
+            open_uri_option("vscode-remote://ssh-remote+host/home/me/notes.md"),
+            "--folder-uri"
+        );
+    }
+
+    #[test]
+    fn workspace_kinds_of_local_and_remote_uris() {
+        let directory = test_directory("workspace-kind");
+        let file = directory.join("notes.md");
+        std::fs::write(&file, "").unwrap();
+        let uri_of = |path: &Path| gio::File::for_path(path).uri().to_string();
+        assert_eq!(
+            WorkspaceKind::of_uri(&uri_of(&directory)),
+            WorkspaceKind::Folder
+        );
+        assert_eq!(WorkspaceKind::of_uri(&uri_of(&file)), WorkspaceKind::File);
+        assert_eq!(
+            WorkspaceKind::of_uri("vscode-remote://ssh-remote+host/home/me/app.code-workspace"),
+            WorkspaceKind::Workspace
+        );
+        assert_eq!(
+            WorkspaceKind::of_uri(&uri_of(&directory.join("missing"))),
+            WorkspaceKind::Folder
+        );
+
+        let description = DescriptionConfig {
+            path: true,
+            kind: true,
+            max_length: None,
+        };
+        assert_eq!(
+            description.describe(&uri_of(&file), "/home/me/notes.md".to_owned()),
+            "File · /home/me/notes.md"
+        );
+        let kind_only = DescriptionConfig {
+            path: false,
+            ..description
+        };
+        assert_eq!(
+            kind_only.describe(&uri_of(&directory), "/home/me".to_owned()),
+            "Folder"
+        );
+    }
+
+    #[test]
+    fn name_and_description_of_codespaces() {
+        let uri = "vscode-remote://codespaces+fuzzy-space-invention-5g4x/workspaces/repo";
+        let remote = named_remote_workspace(uri).unwrap();
+        assert_eq!(remote.label, "Codespace");
+        assert_eq!(remote.name, "fuzzy-space-invention-5g4x");
+        assert_eq!(remote.path, "/workspaces/repo");
+        assert_eq!(
+            name_and_description_of_uri(DisplayConfig::default(), uri),
+            (
+                "repo".to_owned(),
+                "Codespace (fuzzy-space-invention-5g4x): /workspaces/repo".to_owned()
+            )
+        );
+        assert_eq!(
+            name_and_description_of_uri(
+                DisplayConfig::default(),
+                "vscode-remote://codespaces+fuzzy-space-invention-5g4x/"
+            )
+            .0,
+            "fuzzy-space-invention-5g4x"
+        );
+        assert!(named_remote_workspace("vscode-remote://ssh-remote+host/home/me").is_none());
+    }
+
+    #[test]
+    fn name_and_description_of_tunnels() {
+        let uri = "vscode-remote://tunnel+my-desktop/home/me/my%20project";
+        let remote = named_remote_workspace(uri).unwrap();
+        assert_eq!(remote.label, "Tunnel");
+        assert_eq!(remote.name, "my-desktop");
+        assert_eq!(
+            name_and_description_of_uri(DisplayConfig::default(), uri),
+            (
+                "my project".to_owned(),
+                "Tunnel (my-desktop): /home/me/my project".to_owned()
+            )
+        );
+        assert!(named_remote_workspace("file:///home/me/tunnel+my-desktop").is_none());
+    }
+
+    #[test]
+    fn name_suffixes_at_depth_two() {
+        assert_eq!(name_suffix_from_uri("/home/me/src/a/", 2), Some("src/a"));
+        assert_eq!(
+            name_suffix_from_uri("file:///home/me/src/a", 2),
+            Some("src/a")
+        );
+        assert_eq!(name_suffix_from_uri("/a", 2), Some("a"));
+        assert_eq!(name_suffix_from_uri("/", 2), None);
+        let config = DisplayConfig {
+            name_depth: 2,
+            ..DisplayConfig::default()
+        };
+        assert_eq!(
+            name_and_description_of_uri(config, "file:///home/me/src/a/").0,
+            "src/a"
+        );
+    }
+
+    #[test]
+    fn root_workspaces_are_named_after_host_or_slash() {
+        let config = DisplayConfig::default();
+        assert_eq!(name_from_uri("/"), None);
+        assert_eq!(workspace_name(config, "file:///"), "/");
+        assert_eq!(
+            workspace_name(config, "vscode-remote://ssh-remote+devbox/"),
+            "ssh-remote+devbox"
+        );
+        assert_eq!(name_and_description_of_uri(config, "file:///").0, "/");
+    }
+
+    #[test]
+    fn nul_characters_never_reach_results() {
+        let config = DisplayConfig::default();
+        assert_eq!(replace_nul("a\0b\0".to_owned()), "a\u{FFFD}b\u{FFFD}");
+        assert_eq!(replace_nul("ab".to_owned()), "ab");
+        // glib refuses to decode %00, so the description is the URI as it is.
+        assert_eq!(
+            name_and_description_of_uri(config, "file:///home/me/a%00b"),
+            ("a\u{FFFD}b".to_owned(), "file:///home/me/a%00b".to_owned())
+        );
+        let (name, description) = name_and_description_of_uri(config, "file:///home/me/%FF%00");
+        assert_eq!(name, "%FF%00");
+        assert!(!description.contains('\0'), "{description:?}");
+        let workspaces = Workspaces::new(
+            vec![
+                "file:///home/me/a\0b".to_owned(),
+                "file:///home/me/c".to_owned(),
+            ],
+            Vec::new(),
+            false,
+        );
+        assert_eq!(workspaces.ids, ["file:///home/me/c"]);
+    }
+
+    #[test]
+    fn names_decode_final_segments() {
+        let config = DisplayConfig::default();
+        assert_eq!(workspace_name(config, "file:///home/me/caf%C3%A9"), "café");
+        assert_eq!(workspace_name(config, "file:///home/%ZZ/caf%C3%A9"), "café");
+        assert_eq!(workspace_name(config, "file:///home/%ZZ/caf%C3"), "caf%C3");
+    }
+
+    #[test]
+    fn cached_workspaces_miss_when_stamps_change() {
+        let directory = test_directory("cache");
+        std::env::set_var("XDG_STATE_HOME", directory.join("state"));
+        let app_id = "de.swsnr.test.cache";
+        assert!(CachedWorkspaces::path(app_id).starts_with(&directory));
+        let db_path = directory.join("state.vscdb");
+        std::fs::write(&db_path, "").unwrap();
+        let stamps = file_stamps(&[db_path, directory.join("missing.vscdb")]);
+        let uris = vec![
+            "file:///home/me/a".to_owned(),
+            "file:///home/me/b".to_owned(),
+        ];
+        assert_eq!(CachedWorkspaces::read(app_id, &stamps), None);
+        CachedWorkspaces::write(app_id, &stamps, &uris);
+        assert_eq!(CachedWorkspaces::read(app_id, &stamps), Some(uris));
+
+        let mut changed = stamps.clone();
+        changed[0] = changed[0].map(|stamp| FileStamp {
+            modified: stamp.modified + Duration::from_secs(1),
+            ..stamp
+        });
+        assert_eq!(CachedWorkspaces::read(app_id, &changed), None);
+        assert_eq!(CachedWorkspaces::read(app_id, &stamps[..1]), None);
+    }
+
+    #[test]
+    fn file_stamps_change_when_files_get_replaced() {
+        let directory = test_directory("file-stamp");
+        let path = directory.join("state.vscdb");
+        std::fs::write(&path, "old").unwrap();
+        let old = FileStamp::of_path(&path).unwrap();
+        assert_eq!(FileStamp::of_path(&path), Some(old));
+
+        let new_path = directory.join("state.vscdb.new");
+        std::fs::write(&new_path, "new").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&new_path)
+            .unwrap()
+            .set_modified(old.modified)
+            .unwrap();
+        std::fs::rename(&new_path, &path).unwrap();
+        let new = FileStamp::of_path(&path).unwrap();
+        assert_eq!(new.modified, old.modified);
+        assert_ne!(new, old);
+        assert_eq!(FileStamp::of_path(&new_path), None);
+    }
+
+    #[test]
+    fn untitled_workspaces_in_both_locations() {
+        assert!(is_untitled_workspace(
+            "file:///home/me/.config/Code/User/workspaceStorage/3/Untitled-3.code-workspace"
+        ));
+        assert!(is_untitled_workspace(
+            "file:///home/me/.config/Code/Workspaces/2/workspace.json"
+        ));
+        assert!(!is_untitled_workspace("file:///home/me/a.code-workspace"));
+        assert!(!is_untitled_workspace(
+            "file:///home/me/.config/Code/User/workspaceStorage/3/workspace.json"
+        ));
+    }
+
+    #[test]
+    fn apps_without_try_exec_program_are_not_launchable() {
+        let directory = test_directory("try-exec");
+        let program = write_script(&directory, "code", "exit 0");
+        let provider = SearchProvider::new(
+            Application::new(None, ApplicationFlags::empty()),
+            test_variant("de.swsnr.test.try-exec"),
+            desktop_app_info(&format!("TryExec={}\n", program.display())),
+            "/de/swsnr/test".to_owned(),
+            Workspaces::new(Vec::new(), vec![None], false),
+            None,
+            ProviderConfig::default(),
+        );
+        assert!(provider.is_launchable());
+        std::fs::remove_file(&program).unwrap();
+        assert!(!provider.is_launchable());
+        let without_try_exec = test_provider(
+            test_variant("de.swsnr.test.try-exec"),
+            Workspaces::new(Vec::new(), vec![None], false),
+        );
+        assert!(without_try_exec.is_launchable());
+    }
+
+    #[test]
+    fn restricted_mode_adds_restricted_options() {
+        let variant = test_variant("de.swsnr.test.restricted");
+        assert!(variant.launch_options().is_empty());
+        std::env::set_var(variant.environment_variable("RESTRICTED"), "1");
+        assert_eq!(variant.launch_options(), ["--disable-extensions"]);
+        std::env::set_var(variant.environment_variable("RESTRICTED"), "0");
+        assert!(variant.launch_options().is_empty());
+    }
+
+    #[test]
+    fn profile_adds_profile_option_after_restricted_options() {
+        let variant = test_variant("de.swsnr.test.profile");
+        std::env::set_var(variant.environment_variable("PROFILE"), "");
+        assert!(variant.launch_options().is_empty());
+        std::env::set_var(variant.environment_variable("PROFILE"), "Audit Profile");
+        assert_eq!(variant.launch_options(), ["--profile", "Audit Profile"]);
+        std::env::set_var(variant.environment_variable("RESTRICTED"), "1");
+        assert_eq!(
+            variant.launch_options(),
+            ["--disable-extensions", "--profile", "Audit Profile"]
+        );
+    }
+
+    #[test]
+    fn discover_variants_with_recent_workspaces() {
+        let config_dir = test_directory("discover-variants");
+        let create_database = |name: &str, list: Option<serde_json::Value>| {
+            let global_storage = config_dir.join(name).join("User").join("globalStorage");
+            std::fs::create_dir_all(&global_storage).unwrap();
+            let connection =
+                rusqlite::Connection::open(global_storage.join("state.vscdb")).unwrap();
+            match list {
+                Some(list) => database_with_list(&connection, &list),
+                None => database_with_list_under(&connection, "some.other.key", &mixed_list()),
+            }
+        };
+        create_database("Code - Insiders", Some(mixed_list()));
+        create_database("Code", Some(mixed_list()));
+        create_database("Other Editor", None);
+        std::fs::create_dir_all(config_dir.join("Empty")).unwrap();
+
+        let discovered = discover_variants(&config_dir, &VARIANTS);
+        assert_eq!(discovered.len(), 1);
+        assert_eq!(discovered[0].app_id(), "code-insiders");
+        assert_eq!(discovered[0].config_directory_name, "Code - Insiders");
+        assert!(discover_variants(&config_dir.join("missing"), &VARIANTS).is_empty());
+    }
+
+    #[test]
+    fn database_paths_include_profiles_in_stable_order() {
+        let config_dir = test_directory("profiles");
+        let variant = test_variant("de.swsnr.test.profiles");
+        std::env::set_var(variant.environment_variable("CONFIG_DIR"), &config_dir);
+        let default_db = config_dir
+            .join("User")
+            .join("globalStorage")
+            .join("state.vscdb");
+        let profile_db = |profile: &str| {
+            config_dir
                 .join("User")
+                .join("profiles")
+                .join(profile)
                 .join("globalStorage")
-                .join("state.vscdb");
-            glib::info!(
-                "Found app {desktop_id}, loading workspaces from db at {}",
-                db_path.display()
+                .join("state.vscdb")
+        };
+        for profile in ["b2", "a1"] {
+            std::fs::create_dir_all(profile_db(profile).parent().unwrap()).unwrap();
+            std::fs::write(profile_db(profile), "").unwrap();
+        }
+        std::fs::create_dir_all(config_dir.join("User").join("profiles").join("c3")).unwrap();
+        assert_eq!(variant.database_paths(), std::slice::from_ref(&default_db));
+        std::env::set_var(variant.environment_variable("PROFILES"), "1");
+        assert_eq!(
+            variant.database_paths(),
+            [default_db, profile_db("a1"), profile_db("b2")]
+        );
+    }
+
+    #[test]
+    fn workspace_folder_names_of_workspace_files() {
+        let directory = test_directory("workspace-folder-names");
+        let workspace_file = directory.join("app.code-workspace");
+        std::fs::write(
+            &workspace_file,
+            r#"{"folders": [
+                {"path": "frontend", "name": "Web UI"},
+                {"path": "backend"},
+                {"path": "docs", "name": ""}
+            ]}"#,
+        )
+        .unwrap();
+        let commented_file = directory.join("commented.code-workspace");
+        std::fs::write(&commented_file, "// Comment\n{\"folders\": []}").unwrap();
+        let uri = gio::File::for_path(&workspace_file).uri().to_string();
+        assert_eq!(workspace_folder_names(&uri), ["Web UI"]);
+        let commented_uri = gio::File::for_path(&commented_file).uri();
+        assert!(workspace_folder_names(&commented_uri).is_empty());
+        let folder_uri = gio::File::for_path(&directory).uri();
+        assert!(workspace_folder_names(&folder_uri).is_empty());
+
+        let workspaces = Workspaces::new(vec![uri.clone()], Vec::new(), true);
+        let parent = directory.to_str().unwrap();
+        assert_eq!(
+            workspaces.by_id[&canonical_uri(&uri)].decoded_folder_names,
+            [format!("{parent}/Web UI")]
+        );
+    }
+
+    #[test]
+    fn load_workspaces_from_path_rejects_directories() {
+        let directory = test_directory("database-directory");
+        let error = load_workspaces_from_path(&directory, &load_all_options()).unwrap_err();
+        assert!(error.matches(IOErrorEnum::IsDirectory), "{error}");
+        let error =
+            load_workspaces_from_path(Path::new("/dev/null"), &load_all_options()).unwrap_err();
+        assert!(error.matches(IOErrorEnum::NotRegularFile), "{error}");
+
+        let db_path = directory.join("state.vscdb");
+        database_with_list(
+            &rusqlite::Connection::open(&db_path).unwrap(),
+            &mixed_list(),
+        );
+        assert_eq!(
+            load_workspaces_from_path(&db_path, &load_all_options()).unwrap(),
+            ["file:///home/me/folder", "file:///home/me/a.code-workspace"]
+        );
+    }
+
+    #[test]
+    fn limit_search_terms_keeps_the_first_terms() {
+        let terms = (0..100)
+            .map(|index| format!("t{index}"))
+            .collect::<Vec<_>>();
+        let limited = limit_search_terms(terms.clone());
+        assert_eq!(limited, &terms[..MAX_SEARCH_TERMS]);
+        let few = terms[..MAX_SEARCH_TERMS].to_vec();
+        assert_eq!(limit_search_terms(few.clone()), few);
+    }
+
+    #[test]
+    fn launch_result_replaces_empty_results() {
+        let config = ProviderConfig {
+            display: DisplayConfig {
+                launch_result: true,
+                ..DisplayConfig::default()
+            },
+            ..ProviderConfig::default()
+        };
+        let workspaces =
+            || Workspaces::new(vec!["file:///home/me/a".to_owned()], vec![None], false);
+        let provider = configured_test_provider(
+            test_variant("de.swsnr.test.launch"),
+            workspaces(),
+            None,
+            config,
+        );
+        assert_eq!(
+            provider.with_launch_result(Vec::new()),
+            [LAUNCH_APP_RESULT_ID]
+        );
+        assert_eq!(
+            provider.with_launch_result(vec!["file:///home/me/a"]),
+            ["file:///home/me/a"]
+        );
+        let metas = provider.result_metas(&[LAUNCH_APP_RESULT_ID.to_owned()]);
+        assert_eq!(
+            metas[0].lookup::<String>("description").unwrap().as_deref(),
+            Some("Open Code without any workspace")
+        );
+
+        let without = test_provider(test_variant("de.swsnr.test.launch"), workspaces());
+        assert!(without.with_launch_result(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn load_error_result_replaces_all_results() {
+        let provider = configured_test_provider(
+            test_variant("de.swsnr.test.load-error"),
+            Workspaces::new(Vec::new(), vec![None], false),
+            Some("Database is locked".to_owned()),
+            ProviderConfig::default(),
+        );
+        assert_eq!(
+            provider.with_launch_result(Vec::new()),
+            [LOAD_ERROR_RESULT_ID]
+        );
+        assert_eq!(
+            provider.with_launch_result(vec!["file:///home/me/a"]),
+            [LOAD_ERROR_RESULT_ID]
+        );
+        let metas = provider.result_metas(&[LOAD_ERROR_RESULT_ID.to_owned()]);
+        assert_eq!(
+            metas[0].lookup::<String>("description").unwrap().as_deref(),
+            Some("Database is locked")
+        );
+    }
+
+    #[test]
+    fn application_scope_patterns_match_scopes_of_the_app_only() {
+        let patterns = application_scope_patterns("com.visualstudio.code-oss");
+        assert_eq!(
+            patterns,
+            [
+                "app-com.visualstudio.code\\x2doss-*.scope",
+                "app-*-com.visualstudio.code\\x2doss-*.scope"
+            ]
+        );
+        let matches = |name: &str| patterns.iter().any(|pattern| glob_matches(pattern, name));
+        assert!(matches(&scope_unit_name("com.visualstudio.code-oss")));
+        assert!(matches("app-com.visualstudio.code\\x2doss-1234.scope"));
+        assert!(!matches(&scope_unit_name("codium")));
+        assert!(!matches(
+            "app-gnome-com.visualstudio.code\\x2doss-1234.service"
+        ));
+    }
+
+    /// A provider in dry-run mode with `launch` config, for a desktop entry in a directory for `test`.
+    fn dry_run_provider(test: &str, launch: LaunchConfig, uris: &[&str]) -> Rc<SearchProvider> {
+        let directory = test_directory(test);
+        let desktop_file = directory.join("code.desktop");
+        std::fs::write(
+            &desktop_file,
+            "[Desktop Entry]\nType=Application\nName=Code\nExec=sh %F\n",
+        )
+        .unwrap();
+        let workspaces = Workspaces::new(
+            uris.iter().map(|uri| (*uri).to_owned()).collect(),
+            vec![None],
+            false,
+        );
+        SearchProvider::new(
+            Application::new(None, ApplicationFlags::empty()),
+            test_variant(&format!("de.swsnr.test.{test}")),
+            DesktopAppInfo::from_filename(&desktop_file).unwrap(),
+            "/de/swsnr/test".to_owned(),
+            workspaces,
+            None,
+            ProviderConfig {
+                launch: LaunchConfig {
+                    dry_run: true,
+                    ..launch
+                },
+                ..ProviderConfig::default()
+            },
+        )
+    }
+
+    #[test]
+    fn launch_command_runs_in_new_scope_with_uri() {
+        let provider = dry_run_provider("launch-command", LaunchConfig::default(), &[]);
+        run_local(provider.launch_uri(Some("file:///home/me/project"))).unwrap();
+        let command = provider.last_dry_run_command.borrow().clone();
+        assert_eq!(&command[..3], ["/usr/bin/systemd-run", "--user", "--scope"]);
+        let unit_arg = command[3].strip_prefix("--unit=").unwrap();
+        assert!(
+            glob_matches(
+                "app-gnome-de.swsnr.test.launch\\x2dcommand-*.scope",
+                unit_arg
+            ),
+            "{unit_arg}"
+        );
+        assert_eq!(command.last().unwrap(), "file:///home/me/project");
+    }
+
+    /// Connect to the private `bus`, as a client of its message bus.
+    fn test_bus_connection(bus: &gio::TestDBus) -> gio::DBusConnection {
+        gio::DBusConnection::for_address_sync(
+            &bus.bus_address().unwrap(),
+            gio::DBusConnectionFlags::AUTHENTICATION_CLIENT
+                | gio::DBusConnectionFlags::MESSAGE_BUS_CONNECTION,
+            None,
+            gio::Cancellable::NONE,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn has_owner_tells_owned_names() {
+        let bus = gio::TestDBus::new(gio::TestDBusFlags::NONE);
+        bus.up();
+        let connection = test_bus_connection(&bus);
+        assert!(!has_owner(&connection, APP_ID).unwrap());
+        connection
+            .call_sync(
+                Some("org.freedesktop.DBus"),
+                "/org/freedesktop/DBus",
+                "org.freedesktop.DBus",
+                "RequestName",
+                Some(&(APP_ID, 0_u32).to_variant()),
+                Some(glib::VariantTy::new("(u)").unwrap()),
+                gio::DBusCallFlags::NONE,
+                -1,
+                gio::Cancellable::NONE,
+            )
+            .unwrap();
+        assert!(has_owner(&test_bus_connection(&bus), APP_ID).unwrap());
+        connection.close_sync(gio::Cancellable::NONE).unwrap();
+        bus.down();
+    }
+
+    /// A manager of `providers`, none of which is registered on a bus.
+    fn test_manager(providers: &[&Rc<SearchProvider>]) -> Manager {
+        let node = DBusNodeInfo::for_xml(VSCODE_SEARCH_PROVIDER_XML).unwrap();
+        let interfaces = ProviderInterfaces {
+            search_provider: DBusNodeInfo::for_xml(SEARCH_PROVIDER2_XML)
+                .unwrap()
+                .lookup_interface("org.gnome.Shell.SearchProvider2")
+                .unwrap(),
+            variant: node
+                .lookup_interface("de.swsnr.VSCodeSearchProvider.Variant")
+                .unwrap(),
+        };
+        Manager::new(
+            Application::new(None, ApplicationFlags::empty()),
+            interfaces,
+            ProviderConfig::default(),
+            providers
+                .iter()
+                .map(|provider| RegisteredProvider {
+                    provider: Rc::clone(provider),
+                    registration_ids: Vec::new(),
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn open_in_variant_launches_the_requested_variant() {
+        let first = dry_run_provider("open-in-first", LaunchConfig::default(), &[]);
+        let second = dry_run_provider("open-in-second", LaunchConfig::default(), &[]);
+        let manager = test_manager(&[&first, &second]);
+        let open = |app_id: &str| {
+            run_local(
+                manager.handle_call(ManagerMethod::OpenInVariant(OpenInVariant(
+                    app_id.to_owned(),
+                    "file:///home/me/project".to_owned(),
+                ))),
+            )
+        };
+        assert_eq!(open("de.swsnr.test.open-in-second").unwrap(), None);
+        assert!(first.last_dry_run_command.borrow().is_empty());
+        assert_eq!(
+            second.last_dry_run_command.borrow().last().unwrap(),
+            "file:///home/me/project"
+        );
+        let error = open("de.swsnr.test.unknown").unwrap_err();
+        assert!(error.matches(IOErrorEnum::NotFound), "{error}");
+    }
+
+    #[test]
+    fn immutable_database_uris_open_databases() {
+        let directory = test_directory("immutable database");
+        let db_path = directory.join("state.vscdb");
+        database_with_list(
+            &rusqlite::Connection::open(&db_path).unwrap(),
+            &mixed_list(),
+        );
+        let uri = immutable_database_uri(&db_path).unwrap();
+        assert!(uri.starts_with("file:///"), "{uri}");
+        assert!(
+            uri.ends_with("immutable%20database/state.vscdb?immutable=1"),
+            "{uri}"
+        );
+        let connection = rusqlite::Connection::open_with_flags(
+            &uri,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_URI,
+        )
+        .unwrap();
+        assert_eq!(
+            load_workspaces(&connection, &load_all_options()).unwrap(),
+            ["file:///home/me/folder", "file:///home/me/a.code-workspace"]
+        );
+        assert!(!is_on_read_only_filesystem(&db_path));
+    }
+
+    #[test]
+    fn dry_run_records_commands_of_activated_results() {
+        let uri = "file:///home/me/project";
+        let provider = dry_run_provider("dry-run", LaunchConfig::default(), &[uri]);
+        let call = |call| run_local(provider.handle_call(call)).unwrap();
+        assert_eq!(
+            call(SearchProvider2Method::ActivateResult(ActivateResult(
+                uri.to_owned(),
+                vec!["project".to_owned()],
+                0
+            ))),
+            None
+        );
+        let command = provider.last_dry_run_command.borrow().clone();
+        assert_eq!(command[0], "/usr/bin/systemd-run");
+        assert_eq!(
+            &command[command.len() - 3..],
+            [
+                "launch",
+                provider.code_app_info.filename().unwrap().to_str().unwrap(),
+                uri
+            ]
+        );
+
+        call(SearchProvider2Method::LaunchSearch(LaunchSearch(
+            vec!["project".to_owned()],
+            0,
+        )));
+        let command = provider.last_dry_run_command.borrow().clone();
+        assert_eq!(command[command.len() - 2], "launch");
+        assert!(command[command.len() - 1].ends_with("code.desktop"));
+    }
+
+    #[test]
+    fn workspaces_reloaded_carries_the_workspace_count() {
+        let bus = gio::TestDBus::new(gio::TestDBusFlags::NONE);
+        bus.up();
+        let emitter = test_bus_connection(&bus);
+        let listener = test_bus_connection(&bus);
+        let context = glib::MainContext::new();
+        let count = context
+            .with_thread_default(|| {
+                let received = Rc::new(std::cell::Cell::new(None));
+                let subscription = listener.signal_subscribe(
+                    emitter.unique_name().as_deref(),
+                    Some("de.swsnr.VSCodeSearchProvider.Variant"),
+                    Some("WorkspacesReloaded"),
+                    Some("/de/swsnr/test"),
+                    None,
+                    gio::DBusSignalFlags::NONE,
+                    glib::clone!(
+                        #[strong]
+                        received,
+                        move |_, _, _, _, _, parameters| {
+                            received.set(parameters.get::<(u32,)>());
+                        }
+                    ),
+                );
+                // Round-trip to the bus to make sure it added our match rule
+                // before we emit the signal.
+                listener
+                    .call_sync(
+                        Some("org.freedesktop.DBus"),
+                        "/org/freedesktop/DBus",
+                        "org.freedesktop.DBus",
+                        "GetId",
+                        None,
+                        None,
+                        gio::DBusCallFlags::NONE,
+                        -1,
+                        gio::Cancellable::NONE,
+                    )
+                    .unwrap();
+                emit_workspaces_reloaded(&emitter, "/de/swsnr/test", 42).unwrap();
+                while received.get().is_none() {
+                    context.iteration(true);
+                }
+                listener.signal_unsubscribe(subscription);
+                received.get()
+            })
+            .unwrap();
+        assert_eq!(count, Some((42,)));
+        emitter.close_sync(gio::Cancellable::NONE).unwrap();
+        listener.close_sync(gio::Cancellable::NONE).unwrap();
+        bus.down();
+    }
+
+    #[test]
+    fn config_directory_honours_absolute_overrides_only() {
+        let variant = test_variant("de.swsnr.test.config-dir");
+        let variable = variant.environment_variable("CONFIG_DIR");
+        let default = glib::user_config_dir().join("de.swsnr.test.config-dir");
+        assert_eq!(variant.config_directory(), default);
+        std::env::set_var(&variable, "/srv/code/config");
+        assert_eq!(variant.config_directory(), Path::new("/srv/code/config"));
+        assert_eq!(
+            variant.database_path(),
+            Path::new("/srv/code/config/User/globalStorage/state.vscdb")
+        );
+        std::env::set_var(&variable, "relative/config");
+        assert_eq!(variant.config_directory(), default);
+    }
+
+    #[test]
+    fn searches_remember_their_limited_terms() {
+        let provider = test_provider(
+            test_variant("de.swsnr.test.last-search-terms"),
+            Workspaces::new(vec!["file:///home/me/acme".to_owned()], vec![None], false),
+        );
+        assert!(provider.last_search_terms.borrow().is_empty());
+        let terms = (0..20).map(|n| format!("term{n}")).collect::<Vec<_>>();
+        run_local(
+            provider.handle_call(SearchProvider2Method::GetInitialResultSet(
+                GetInitialResultSet(terms.clone()),
+            )),
+        )
+        .unwrap();
+        assert_eq!(
+            *provider.last_search_terms.borrow(),
+            terms[..MAX_SEARCH_TERMS]
+        );
+
+        run_local(
+            provider.handle_call(SearchProvider2Method::GetSubsearchResultSet(
+                GetSubsearchResultSet(
+                    vec!["file:///home/me/acme".to_owned()],
+                    vec!["acme".to_owned()],
+                ),
+            )),
+        )
+        .unwrap();
+        assert_eq!(*provider.last_search_terms.borrow(), ["acme"]);
+    }
+
+    #[test]
+    fn empty_icon_keys_use_the_fallback_icon() {
+        let fallback = gio::ThemedIcon::from_names(&["text-editor", "text-editor"])
+            .serialize()
+            .unwrap();
+        for icon in ["Icon=\n", "Icon=  \n"] {
+            let provider = SearchProvider::new(
+                Application::new(None, ApplicationFlags::empty()),
+                test_variant("de.swsnr.test.empty-icon"),
+                desktop_app_info(icon),
+                "/de/swsnr/test".to_owned(),
+                Workspaces::new(Vec::new(), vec![None], false),
+                None,
+                ProviderConfig::default(),
             );
-            match open_connection(&db_path).and_then(|c| load_workspaces(&c)) {
-                Ok(workspaces) => {
-                    glib::info!("Found {} workspaces for {desktop_id}, exposing search provider at {object_path}", workspaces.len());
-                    let provider = SearchProvider::new(app.clone(), vscode_app, workspaces);
-                    if let Err(error) = provider.register(&connection, &object_path, &interface) {
-                        glib::error!(
-                            "Skipping {desktop_id}, failed to register on {}, {error}",
-                            object_path,
+            assert_eq!(provider.app_icon(), Some(fallback.clone()), "{icon:?}");
+        }
+    }
+
+    #[test]
+    fn desktop_entries_refer_to_app_ids_by_wm_class_or_executable() {
+        let app_info = desktop_app_info("StartupWMClass=Code-OSS\n");
+        assert!(refers_to_app_id(&app_info, "code-oss"));
+        assert!(refers_to_app_id(&app_info, "Code-OSS"));
+        assert!(!refers_to_app_id(&app_info, "code"));
+        // The executable of test entries is sh.
+        assert!(refers_to_app_id(&app_info, "sh"));
+        assert!(!refers_to_app_id(&desktop_app_info(""), "code-oss"));
+    }
+
+    thread_local! {
+        /// Fields of log records captured on this thread, see [`capture_log_records`].
+        static CAPTURED_LOG_RECORDS: RefCell<Option<Vec<HashMap<String, String>>>> =
+            const { RefCell::new(None) };
+    }
+
+    /// Run `f` and return the fields of all log records it wrote on this thread.
+    ///
+    /// Route Rust logging to Glib like `main` does, and write records of other
+    /// threads and outside of `f` as usual.
+    fn capture_log_records<R>(f: impl FnOnce() -> R) -> (R, Vec<HashMap<String, String>>) {
+        static LOGGER: glib::GlibLogger = glib::GlibLogger::new(
+            glib::GlibLoggerFormat::Structured,
+            glib::GlibLoggerDomain::CrateTarget,
+        );
+        static WRITER: std::sync::Once = std::sync::Once::new();
+        WRITER.call_once(|| {
+            log::set_logger(&LOGGER).unwrap();
+            log::set_max_level(log::LevelFilter::Trace);
+            glib::log_set_writer_func(|level, fields| {
+                CAPTURED_LOG_RECORDS.with_borrow_mut(|records| match records {
+                    Some(records) => {
+                        records.push(
+                            fields
+                                .iter()
+                                .filter_map(|field| {
+                                    Some((field.key().to_owned(), field.value_str()?.to_owned()))
+                                })
+                                .collect(),
                         );
+                        glib::LogWriterOutput::Handled
                     }
-                }
-                Err(error) => {
-                    glib::error!(
-                        "Skipping {desktop_id}, failed to load workspaces from {}: {error}",
-                        db_path.display()
-                    );
-                }
-            }
-        }
+                    None => glib::log_writer_default(level, fields),
+                })
+            });
+        });
+        CAPTURED_LOG_RECORDS.set(Some(Vec::new()));
+        let result = f();
+        let records = CAPTURED_LOG_RECORDS.take().unwrap();
+        (result, records)
     }
-}
 
-pub fn main() -> glib::ExitCode {
-    static LOGGER: glib::GlibLogger = glib::GlibLogger::new(
-        glib::GlibLoggerFormat::Structured,
-        glib::GlibLoggerDomain::CrateTarget,
-    );
-    log::set_logger(&LOGGER).unwrap();
-    log::set_max_level(log::LevelFilter::Trace);
+    #[test]
+    fn search_logs_record_matched_and_total_workspaces() {
+        let ((), records) = capture_log_records(|| {
+            log_search("de.swsnr.test", &["foo".to_owned()], 2, 5);
+        });
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record["N_MATCHED"], "2");
+        assert_eq!(record["N_TOTAL"], "5");
+        assert_eq!(record["SEARCH_TERMS"], r#"["foo"]"#);
+        assert_eq!(record["GLIB_DOMAIN"], G_LOG_DOMAIN);
+        assert_eq!(
+            record["MESSAGE"],
+            r#"2 of 5 workspaces of de.swsnr.test match ["foo"]"#
+        );
+    }
 
-    let app = gio::Application::builder()
-        .application_id("de.swsnr.VSCodeSearchProvider")
-        .flags(ApplicationFlags::IS_SERVICE)
-        // Exit one minute after release the app, i.e. in our case after finishing
-        // the last DBus call.
-        .inactivity_timeout(Duration::from_secs(60).as_millis().try_into().unwrap())
-        .build();
+    #[test]
+    fn result_metas_use_the_fallback_icon_of_the_variant() {
+        let mut variant = test_variant("de.swsnr.test.fallback-icon");
+        variant.fallback_icon = "de.swsnr.test.fallback";
+        // The desktop entry of test providers has no icon.
+        let provider = test_provider(
+            variant,
+            Workspaces::new(vec!["file:///home/me/acme".to_owned()], vec![None], false),
+        );
+        let id = provider.workspaces.borrow().ids[0].clone();
+        let metas = provider.result_metas(&[id]);
+        assert_eq!(
+            metas[0].lookup_value("icon", None),
+            gio::ThemedIcon::from_names(&["de.swsnr.test.fallback", "text-editor"]).serialize()
+        );
+    }
 
-    app.set_version(env!("CARGO_PKG_VERSION"));
-    app.connect_startup(startup);
-    app.run()
+    #[test]
+    fn no_scope_launches_without_systemd_run() {
+        let uri = "file:///home/me/project";
+        let launch = LaunchConfig {
+            no_scope: true,
+            ..LaunchConfig::default()
+        };
+        let provider = dry_run_provider("no-scope", launch, &[uri]);
+        run_local(
+            provider.handle_call(SearchProvider2Method::ActivateResult(ActivateResult(
+                uri.to_owned(),
+                Vec::new(),
+                0,
+            ))),
+        )
+        .unwrap();
+        assert_eq!(
+            *provider.last_dry_run_command.borrow(),
+            [
+                "/usr/bin/gio",
+                "launch",
+                provider.code_app_info.filename().unwrap().to_str().unwrap(),
+                uri
+            ]
+        );
+    }
+
+    #[test]
+    fn result_metas_of_no_identifiers_skip_the_app_icon() {
+        let provider = test_provider(
+            test_variant("de.swsnr.test.no-metas"),
+            Workspaces::new(vec!["file:///home/me/acme".to_owned()], vec![None], false),
+        );
+        let reply = run_local(provider.handle_call(SearchProvider2Method::GetResultMetas(
+            GetResultMetas(Vec::new()),
+        )))
+        .unwrap()
+        .unwrap();
+        assert_eq!(reply.type_(), glib::VariantTy::new("aa{sv}").unwrap());
+        assert_eq!(reply.n_children(), 0);
+        assert!(provider.app_icon.get().is_none());
+    }
+
+    #[test]
+    fn serving_no_variants_warns() {
+        let ((), records) = capture_log_records(|| {
+            log_served_variants(2, 3);
+            log_served_variants(0, 3);
+        });
+        let messages = records
+            .iter()
+            .map(|record| (record["PRIORITY"].as_str(), record["MESSAGE"].as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                ("6", "Serving 2 of 3 variants"),
+                ("4", "Not serving any of 3 variants, see previous messages")
+            ]
+        );
+    }
+
+    #[test]
+    fn get_info_replies_with_version_commit_and_variants() {
+        let first = test_provider(
+            test_variant("de.swsnr.test.info-first"),
+            Workspaces::new(Vec::new(), vec![None], false),
+        );
+        let second = test_provider(
+            test_variant("de.swsnr.test.info-second"),
+            Workspaces::new(Vec::new(), vec![None], false),
+        );
+        let manager = test_manager(&[&first, &second]);
+        let reply = run_local(manager.handle_call(ManagerMethod::GetInfo(GetInfo)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(reply.type_(), glib::VariantTy::new("(ssas)").unwrap());
+        let (version, commit, variants) = reply.get::<(String, String, Vec<String>)>().unwrap();
+        assert_eq!(version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(commit, GIT_COMMIT.unwrap_or_default());
+        assert_eq!(
+            variants,
+            ["de.swsnr.test.info-first", "de.swsnr.test.info-second"]
+        );
+    }
+
+    #[test]
+    fn searches_log_the_database_path() {
+        let variant = test_variant("de.swsnr.test.search-database-log");
+        let db_path = test_directory("search-database-log").join("state.vscdb");
+        std::env::set_var(variant.environment_variable("DB"), &db_path);
+        let provider = test_provider(variant, Workspaces::new(Vec::new(), vec![None], false));
+        let (_, records) = capture_log_records(|| {
+            run_local(
+                provider.handle_call(SearchProvider2Method::GetInitialResultSet(
+                    GetInitialResultSet(vec!["acme".to_owned()]),
+                )),
+            )
+            .unwrap()
+        });
+        let db_path = db_path.display().to_string();
+        assert!(
+            records
+                .iter()
+                .any(|record| record["PRIORITY"] == "7" && record["MESSAGE"].contains(&db_path)),
+            "{records:?}"
+        );
+    }
+
+    #[test]
+    fn compact_mode_empties_descriptions_of_workspaces_only() {
+        let descriptions = |path: bool| {
+            let mut config = ProviderConfig::default();
+            config.display.description.path = path;
+            let provider = configured_test_provider(
+                test_variant("de.swsnr.test.compact"),
+                Workspaces::new(vec!["file:///home/me/acme".to_owned()], vec![None], false),
+                None,
+                config,
+            );
+            let id = provider.workspaces.borrow().ids[0].clone();
+            provider
+                .result_metas(&[id, LAUNCH_APP_RESULT_ID.to_owned()])
+                .iter()
+                .map(|metas| metas.lookup::<String>("description").unwrap().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            descriptions(true),
+            ["/home/me/acme", "Open Code without any workspace"]
+        );
+        assert_eq!(descriptions(false), ["", "Open Code without any workspace"]);
+    }
+
+    #[test]
+    fn clipboard_text_of_local_and_remote_uris() {
+        assert_eq!(
+            clipboard_text_of_uri("file:///home/me/a%20b"),
+            "/home/me/a b"
+        );
+        assert_eq!(
+            clipboard_text_of_uri("vscode-remote://ssh-remote+host/home/me/a"),
+            "vscode-remote://ssh-remote+host/home/me/a"
+        );
+    }
+
+    #[test]
+    fn result_metas_have_clipboard_text_only_if_enabled() {
+        let clipboard_texts = |clipboard_text: bool| {
+            let mut config = ProviderConfig::default();
+            config.display.clipboard_text = clipboard_text;
+            let provider = configured_test_provider(
+                test_variant("de.swsnr.test.clipboard-text"),
+                Workspaces::new(vec!["file:///home/me/a%20b".to_owned()], vec![None], false),
+                None,
+                config,
+            );
+            let id = provider.workspaces.borrow().ids[0].clone();
+            provider
+                .result_metas(&[id, LAUNCH_APP_RESULT_ID.to_owned()])
+                .iter()
+                .map(|metas| metas.lookup::<String>("clipboardText").unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            clipboard_texts(true),
+            [Some("/home/me/a b".to_owned()), None]
+        );
+        assert_eq!(clipboard_texts(false), [None, None]);
+    }
+
+    #[test]
+    fn list_providers_replies_with_object_paths_app_ids_and_names() {
+        let provider = test_provider(
+            test_variant("de.swsnr.test.list-providers"),
+            Workspaces::new(Vec::new(), vec![None], false),
+        );
+        let manager = test_manager(&[&provider]);
+        let reply = run_local(manager.handle_call(ManagerMethod::ListProviders(ListProviders)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(reply.type_(), glib::VariantTy::new("(a(oss))").unwrap());
+        let (providers,) = reply
+            .get::<(Vec<(glib::variant::ObjectPath, String, String)>,)>()
+            .unwrap();
+        assert_eq!(providers.len(), 1);
+        let (object_path, app_id, name) = &providers[0];
+        assert_eq!(object_path.as_str(), "/de/swsnr/test");
+        assert_eq!(app_id, "de.swsnr.test.list-providers");
+        assert_eq!(name, "Code");
+    }
 }
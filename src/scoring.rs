@@ -0,0 +1,1009 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Score workspace URIs against search terms.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+
+use crate::G_LOG_DOMAIN;
+
+/// How to weigh the position of a match within a URI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PositionWeighting {
+    /// Weigh matches linearly by how far to the right they appear.
+    #[default]
+    Linear,
+    /// Weigh matches quadratically by how far to the right they appear.
+    ///
+    /// Matches in the last segments dominate much more decisively over matches
+    /// near the root.
+    Quadratic,
+}
+
+impl PositionWeighting {
+    /// Weigh the relative `position` of a match, between 0 and 1.
+    fn weigh(self, position: f64) -> f64 {
+        match self {
+            PositionWeighting::Linear => position,
+            PositionWeighting::Quadratic => position * position,
+        }
+    }
+}
+
+/// How to group ranked URIs, see [`find_matching_uris`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Grouping {
+    /// Keep URIs in the order of their scores.
+    #[default]
+    None,
+    /// Keep URIs with the same parent directory together.
+    ///
+    /// See [`group_by_parent`].
+    Parent,
+}
+
+/// Search the most recent URIs first, see [`find_matching_uris_tiered`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecentTier {
+    /// How many of the most recent URIs to search first.
+    pub size: usize,
+    /// How many matches among the most recent URIs suffice to skip all other URIs.
+    pub min_results: usize,
+}
+
+/// How to weigh matches by the path segment they appear in.
+///
+/// See [`SegmentWeights::weigh`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SegmentWeights {
+    /// The weight of matches in the last segment, i.e. the base name.
+    pub basename: f64,
+    /// The weight of matches in the segment before the last, i.e. the immediate parent.
+    pub parent: f64,
+    /// The weight of matches in all other segments.
+    pub rest: f64,
+}
+
+impl Default for SegmentWeights {
+    fn default() -> Self {
+        Self {
+            basename: 1.0,
+            parent: 0.5,
+            rest: 0.25,
+        }
+    }
+}
+
+impl SegmentWeights {
+    /// Weigh the `score` of a match at `index` in `uri` by its path segment.
+    ///
+    /// Ignore trailing slashes of `uri`, which do not start another segment.
+    fn weigh(self, uri: &str, index: usize, score: f64) -> f64 {
+        let weight = match uri[index..].trim_end_matches('/').matches('/').count() {
+            0 => self.basename,
+            1 => self.parent,
+            _ => self.rest,
+        };
+        score * weight
+    }
+}
+
+/// The default for [`RecentTier::min_results`].
+const DEFAULT_RECENT_TIER_MIN_RESULTS: usize = 3;
+
+/// Configuration for scoring URIs.
+#[derive(Debug, Clone, Copy)]
+pub struct ScoringConfig {
+    /// How to weigh the position of matches.
+    pub position_weighting: PositionWeighting,
+    /// How to weigh matches by their path segment.
+    pub segment_weights: SegmentWeights,
+    /// Whether to search the most recent URIs first, and how.
+    pub recent_tier: Option<RecentTier>,
+    /// Whether to match case-sensitively.
+    pub case_sensitive: bool,
+    /// The gap penalty for fuzzy matches, or `None` to disable fuzzy matching.
+    ///
+    /// See [`score_term_fuzzy`].
+    pub fuzzy_gap_penalty: Option<f64>,
+    /// The minimum number of characters of the longest search term.
+    ///
+    /// See [`find_matching_uris`].
+    pub min_term_length: usize,
+    /// Whether to boost matches at word boundaries within path segments.
+    ///
+    /// See [`is_word_start`].
+    pub word_boundaries: bool,
+    /// Whether to ignore word separators in URIs and terms.
+    ///
+    /// See [`WORD_SEPARATORS`].
+    pub ignore_separators: bool,
+    /// How to group matching URIs.
+    pub grouping: Grouping,
+    /// The minimum score of a match, relative to the score of the best match.
+    ///
+    /// Matches which score less than this fraction of the best match are too
+    /// weak to be relevant, and get dropped.  `None` keeps all matches.
+    pub relative_score_threshold: Option<f64>,
+}
+
+impl Default for ScoringConfig {
+    fn default() -> Self {
+        Self {
+            position_weighting: PositionWeighting::default(),
+            segment_weights: SegmentWeights::default(),
+            recent_tier: None,
+            case_sensitive: false,
+            fuzzy_gap_penalty: None,
+            min_term_length: 1,
+            word_boundaries: false,
+            ignore_separators: false,
+            grouping: Grouping::None,
+            relative_score_threshold: None,
+        }
+    }
+}
+
+/// The default gap penalty for fuzzy matching.
+const DEFAULT_FUZZY_GAP_PENALTY: f64 = 1.0;
+
+impl ScoringConfig {
+    /// Read the scoring configuration from the environment.
+    ///
+    /// `$VSCODE_SEARCH_PROVIDER_POSITION_WEIGHTING` selects the position
+    /// weighting, either `linear` or `quadratic`.
+    ///
+    /// `$VSCODE_SEARCH_PROVIDER_PARENT_WEIGHT` and
+    /// `$VSCODE_SEARCH_PROVIDER_PATH_WEIGHT` set the weights of matches in the
+    /// immediate parent and in all other segments, see [`SegmentWeights`].
+    ///
+    /// `$VSCODE_SEARCH_PROVIDER_RECENT_TIER_SIZE` enables the recent tier with
+    /// the given size, and `$VSCODE_SEARCH_PROVIDER_RECENT_TIER_MIN_RESULTS`
+    /// sets its minimum number of results.
+    ///
+    /// `$VSCODE_SEARCH_PROVIDER_CASE_SENSITIVE=1` enables case-sensitive matching.
+    ///
+    /// `$VSCODE_SEARCH_PROVIDER_FUZZY=1` enables fuzzy matching, and
+    /// `$VSCODE_SEARCH_PROVIDER_FUZZY_GAP_PENALTY` sets its gap penalty.
+    ///
+    /// `$VSCODE_SEARCH_PROVIDER_MIN_TERM_LENGTH` sets the minimum length of
+    /// search terms.
+    ///
+    /// `$VSCODE_SEARCH_PROVIDER_WORD_BOUNDARIES=1` boosts matches at word
+    /// boundaries.
+    ///
+    /// `$VSCODE_SEARCH_PROVIDER_IGNORE_SEPARATORS=1` ignores word separators.
+    ///
+    /// `$VSCODE_SEARCH_PROVIDER_GROUP_BY_DIRECTORY=1` groups matches by their
+    /// parent directory.
+    ///
+    /// `$VSCODE_SEARCH_PROVIDER_RELATIVE_SCORE_THRESHOLD` drops matches which
+    /// score less than this fraction of the best match.
+    ///
+    /// Use defaults for unset variables, and ignore invalid values.
+    pub fn from_environment() -> Self {
+        let mut config = Self {
+            case_sensitive: crate::environment_flag("VSCODE_SEARCH_PROVIDER_CASE_SENSITIVE"),
+            word_boundaries: crate::environment_flag("VSCODE_SEARCH_PROVIDER_WORD_BOUNDARIES"),
+            ignore_separators: crate::environment_flag("VSCODE_SEARCH_PROVIDER_IGNORE_SEPARATORS"),
+            ..Self::default()
+        };
+        match std::env::var("VSCODE_SEARCH_PROVIDER_POSITION_WEIGHTING").as_deref() {
+            Ok("linear") => config.position_weighting = PositionWeighting::Linear,
+            Ok("quadratic") => config.position_weighting = PositionWeighting::Quadratic,
+            Ok(other) => glib::warn!("Ignoring unknown position weighting {other}"),
+            Err(_) => {}
+        }
+        if crate::environment_flag("VSCODE_SEARCH_PROVIDER_GROUP_BY_DIRECTORY") {
+            config.grouping = Grouping::Parent;
+        }
+        config.relative_score_threshold =
+            weight_from_environment("VSCODE_SEARCH_PROVIDER_RELATIVE_SCORE_THRESHOLD");
+        if let Some(weight) = weight_from_environment("VSCODE_SEARCH_PROVIDER_PARENT_WEIGHT") {
+            config.segment_weights.parent = weight;
+        }
+        if let Some(weight) = weight_from_environment("VSCODE_SEARCH_PROVIDER_PATH_WEIGHT") {
+            config.segment_weights.rest = weight;
+        }
+        if let Some(size) =
+            crate::positive_number_from_environment("VSCODE_SEARCH_PROVIDER_RECENT_TIER_SIZE")
+        {
+            let min_results = crate::positive_number_from_environment(
+                "VSCODE_SEARCH_PROVIDER_RECENT_TIER_MIN_RESULTS",
+            )
+            .unwrap_or(DEFAULT_RECENT_TIER_MIN_RESULTS);
+            config.recent_tier = Some(RecentTier { size, min_results });
+        }
+        if let Some(length) =
+            crate::positive_number_from_environment("VSCODE_SEARCH_PROVIDER_MIN_TERM_LENGTH")
+        {
+            config.min_term_length = length;
+        }
+        if crate::environment_flag("VSCODE_SEARCH_PROVIDER_FUZZY") {
+            let penalty = match std::env::var("VSCODE_SEARCH_PROVIDER_FUZZY_GAP_PENALTY") {
+                Ok(value) => match value.parse::<f64>() {
+                    Ok(penalty) if 0.0 <= penalty => penalty,
+                    _ => {
+                        glib::warn!("Ignoring invalid fuzzy gap penalty {value}");
+                        DEFAULT_FUZZY_GAP_PENALTY
+                    }
+                },
+                Err(_) => DEFAULT_FUZZY_GAP_PENALTY,
+            };
+            config.fuzzy_gap_penalty = Some(penalty);
+        }
+        config
+    }
+}
+
+/// Read a weight between 0 and 1 from the environment variable `name`.
+///
+/// Return `None` if `name` is unset, or ignore its value and return `None` if it
+/// is no number between 0 and 1.
+fn weight_from_environment(name: &str) -> Option<f64> {
+    let value = std::env::var(name).ok()?;
+    match value.parse::<f64>() {
+        Ok(weight) if (0.0..=1.0).contains(&weight) => Some(weight),
+        _ => {
+            glib::warn!("Ignoring invalid ${name} {value}, expected a number between 0 and 1");
+            None
+        }
+    }
+}
+
+/// The boost for a term which matches right at the start of a path segment.
+///
+/// Large enough for a prefix of a segment to outrank matches in the middle of
+/// some segment further to the right.
+const SEGMENT_PREFIX_BOOST: f64 = 0.5;
+
+/// Whether `index` denotes the start of a path segment in `uri`.
+fn is_segment_start(uri: &str, index: usize) -> bool {
+    index == 0 || uri[..index].ends_with('/')
+}
+
+/// The boost for a term which matches right at the start of a word within a path segment.
+///
+/// Smaller than [`SEGMENT_PREFIX_BOOST`], so that prefixes of whole segments
+/// still win.
+const WORD_PREFIX_BOOST: f64 = 0.25;
+
+/// Whether `index` denotes the start of a word within a path segment of `uri`.
+///
+/// Words start after `_`, `-`, and `.`, as well as at upper-case letters which
+/// follow lower-case letters in `original`, i.e. in camel case.  `original` is
+/// `uri` before normalizing its case, see [`score_uri`]; ignore camel case if
+/// normalizing changed the length of `uri`, because `index` then doesn't refer
+/// to the same character in `original`.
+fn is_word_start(uri: &str, original: &str, index: usize) -> bool {
+    let previous = uri[..index].chars().next_back();
+    if previous.is_some_and(|c| matches!(c, '_' | '-' | '.')) {
+        return true;
+    }
+    if original.len() != uri.len() || !original.is_char_boundary(index) {
+        return false;
+    }
+    let previous = original[..index].chars().next_back();
+    let current = original[index..].chars().next();
+    previous.is_some_and(char::is_lowercase) && current.is_some_and(char::is_uppercase)
+}
+
+/// Calculate how well `uri` matches a single `term`.
+///
+/// Score every match of `term` according to how far to the right it appears in
+/// `uri`, weighed as per `config`, and boost matches at the start of a path
+/// segment, see [`SEGMENT_PREFIX_BOOST`].  If `config` asks for word boundaries,
+/// boost matches at the start of a word in `original`, see [`is_word_start`] and
+/// [`WORD_PREFIX_BOOST`].  Then weigh every match by the path
+/// segment it starts in, see [`SegmentWeights`].  Return the best score of all
+/// matches, or `None` if `term` does not match at all.
+#[allow(
+    clippy::cast_precision_loss,
+    reason = "URIs won't grow so large as to cause issues in f64 conversion"
+)]
+fn score_term(config: ScoringConfig, uri: &str, original: &str, term: &str) -> Option<f64> {
+    uri.rmatch_indices(term)
+        .map(|(index, _)| {
+            // We add 1 to avoid returning zero if the term matches right at the beginning.
+            let position = (index + 1) as f64 / uri.len() as f64;
+            let score = config.position_weighting.weigh(position);
+            let score = if is_segment_start(uri, index) {
+                score + SEGMENT_PREFIX_BOOST
+            } else if config.word_boundaries && is_word_start(uri, original, index) {
+                score + WORD_PREFIX_BOOST
+            } else {
+                score
+            };
+            config.segment_weights.weigh(uri, index, score)
+        })
+        .reduce(f64::max)
+}
+
+/// Calculate how well `uri` matches the characters of `term` in order, with gaps.
+///
+/// Find the tightest subsequence of `uri` which matches `term`, preferring the
+/// right-most one among equally tight subsequences.  Score it by the position of
+/// its end like [`score_term`], weighed by the path segment of its end, and
+/// divided by one plus `gap_penalty` times the total number of characters
+/// between matched characters.  Tight subsequences
+/// score well, and scattered ones close to zero.
+///
+/// Return `None` if `term` does not match at all.
+#[allow(
+    clippy::cast_precision_loss,
+    reason = "URIs won't grow so large as to cause issues in f64 conversion"
+)]
+fn score_term_fuzzy(config: ScoringConfig, gap_penalty: f64, uri: &str, term: &str) -> Option<f64> {
+    let uri_chars = uri.chars().collect::<Vec<_>>();
+    let term_chars = term.chars().collect::<Vec<_>>();
+    let (first, rest) = term_chars.split_first()?;
+    let mut tightest: Option<(usize, usize)> = None;
+    for (start, _) in uri_chars.iter().enumerate().filter(|(_, c)| *c == first) {
+        let end = rest.iter().try_fold(start, |position, c| {
+            uri_chars[position + 1..]
+                .iter()
+                .position(|uri_char| uri_char == c)
+                .map(|offset| position + 1 + offset)
+        });
+        if let Some(end) = end {
+            let gap = end + 1 - start - term_chars.len();
+            if tightest.map_or(true, |(tightest_gap, _)| gap <= tightest_gap) {
+                tightest = Some((gap, end));
+            }
+        }
+    }
+    let (gap, end) = tightest?;
+    let position = (end + 1) as f64 / uri_chars.len() as f64;
+    let end_index = uri.char_indices().nth(end).map_or(0, |(index, _)| index);
+    let score =
+        config
+            .segment_weights
+            .weigh(uri, end_index, config.position_weighting.weigh(position));
+    Some(score / (1.0 + gap_penalty * gap as f64))
+}
+
+/// The extension of workspace files.
+pub const WORKSPACE_EXTENSION: &str = ".code-workspace";
+
+/// Characters which separate words in names of workspaces.
+///
+/// With [`ScoringConfig::ignore_separators`] we remove these from URIs and
+/// terms before matching, so that e.g. `my-project` matches `my_project`,
+/// `my project`, and `myProject` alike.
+const WORD_SEPARATORS: [char; 3] = ['-', '_', ' '];
+
+/// Remove all [`WORD_SEPARATORS`] from `text` if `config` asks to ignore them.
+fn remove_separators(config: ScoringConfig, text: &str) -> Cow<'_, str> {
+    if config.ignore_separators {
+        Cow::Owned(text.replace(WORD_SEPARATORS, ""))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// The score of a URI against search terms, see [`score_uri`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct UriScore {
+    /// Whether any term matched only fuzzily, see [`score_term_fuzzy`].
+    fuzzy: bool,
+    /// How well the URI matches all terms.
+    value: f64,
+}
+
+/// Calculate how well `uri` matches all of the given `terms`.
+///
+/// Ignore the extension of workspace files, see [`WORKSPACE_EXTENSION`], which
+/// would otherwise dilute matches on the name of the workspace.
+///
+/// The URI gets scored for each term according to how far to the right it appears in the URI,
+/// under the assumption that the right most part of an URI path is the most specific.  Terms
+/// which match a prefix of a path segment score higher, see [`score_term`], and
+/// matches in the base name dominate matches in the immediate parent, which in
+/// turn dominate matches in all other segments, see [`SegmentWeights`].  If
+/// `config` enables fuzzy matching, terms which do not match contiguously may
+/// still match fuzzily, see [`score_term_fuzzy`].
+///
+/// All matches are done on the lowercase text, i.e. case-insensitive, unless
+/// `config` asks for case-sensitive matching.  If `config` asks to ignore word
+/// separators, remove all [`WORD_SEPARATORS`] from `uri` and `terms` first;
+/// matches then no longer start at words after `_` or `-`, see [`is_word_start`].
+///
+/// Return a positive score if all of `terms` match `uri`.  The higher the score the
+/// better the match, in relation to other matching values.  In and by itself however
+/// the score has no intrinsic meaning.
+///
+/// If one term out of `terms` does not match `uri` return `None`, regardless
+/// of how well other terms match.
+fn score_uri<S: AsRef<str>>(config: ScoringConfig, uri: &str, terms: &[S]) -> Option<UriScore> {
+    let normalize = |text: &str| {
+        if config.case_sensitive {
+            text.to_owned()
+        } else {
+            text.to_lowercase()
+        }
+    };
+    let original = remove_separators(config, uri.strip_suffix(WORKSPACE_EXTENSION).unwrap_or(uri));
+    let uri = normalize(&original);
+    let initial = UriScore {
+        fuzzy: false,
+        value: 0.0,
+    };
+    terms.iter().try_fold(initial, |score, term| {
+        let term = normalize(&remove_separators(config, term.as_ref()));
+        if let Some(term_score) = score_term(config, &uri, &original, &term) {
+            Some(UriScore {
+                value: score.value + term_score,
+                ..score
+            })
+        } else {
+            let gap_penalty = config.fuzzy_gap_penalty?;
+            score_term_fuzzy(config, gap_penalty, &uri, &term).map(|term_score| UriScore {
+                fuzzy: true,
+                value: score.value + term_score,
+            })
+        }
+    })
+}
+
+/// Find all URIs from `uris` which match all of `terms`.
+///
+/// `uris` yields pairs of URIs and their decoded form, see [`crate::decode_uri`]; every
+/// URI gets scored by its decoded form.  Both are borrowed, and so are all
+/// returned URIs, so searching never copies any URI; it only allocates for
+/// the scores of matching URIs, and for normalizing case.  Filter out all URIs with a score of 0
+/// or less, and, if `config` has a relative score threshold, all URIs which
+/// score below this fraction of the best match, see
+/// [`ScoringConfig::relative_score_threshold`].
+///
+/// Contiguous matches always win: Return all URIs which only match fuzzily after
+/// all other URIs, and compare their scores only among each other.
+///
+/// `uris` may yield the same URI more than once, with different decoded forms;
+/// return every URI only once, at the rank of its best decoded form.
+///
+/// If `config` asks for grouping, group URIs after ranking them, see [`Grouping`].
+///
+/// If the longest of `terms` has less characters than the minimum term length
+/// of `config`, return no URIs at all, without scoring any URI; such short
+/// terms match almost every URI anyway, even many of them together.
+pub fn find_matching_uris<'a, I, S>(config: ScoringConfig, uris: I, terms: &[S]) -> Vec<&'a str>
+where
+    S: AsRef<str> + Debug,
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let term_length = terms
+        .iter()
+        .map(|term| term.as_ref().chars().count())
+        .max()
+        .unwrap_or_default();
+    if term_length < config.min_term_length {
+        glib::debug!(
+            "Terms {terms:?} shorter than {} characters, not searching",
+            config.min_term_length
+        );
+        return Vec::new();
+    }
+    let mut scored = uris
+        .into_iter()
+        .filter_map(|(uri, decoded_uri)| {
+            let score = score_uri(config, decoded_uri, terms)?;
+            glib::trace!("URI {decoded_uri} scores {score:?} against {terms:?}");
+            if score.value <= 0.0 {
+                None
+            } else {
+                Some((score, uri))
+            }
+        })
+        .collect::<Vec<_>>();
+    let best_score = |fuzzy: bool| {
+        scored
+            .iter()
+            .filter(|(score, _)| score.fuzzy == fuzzy)
+            .map(|(score, _)| score.value)
+            .fold(0.0, f64::max)
+    };
+    let (best_exact_score, best_fuzzy_score) = (best_score(false), best_score(true));
+    scored.retain(|(score, _)| {
+        let best_score = if score.fuzzy {
+            best_fuzzy_score
+        } else {
+            best_exact_score
+        };
+        config
+            .relative_score_threshold
+            .map_or(true, |threshold| best_score * threshold <= score.value)
+    });
+    // `sort_by_key` is stable, so URIs with equal coarse scores keep the order of
+    // `uris`, i.e. the order of recency for initial searches, and the order of
+    // previous results for subsearches.  This keeps results from reordering
+    // between keystrokes.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::as_conversions,
+        reason = "Truncation intended to calculate a coarse ordering score"
+    )]
+    scored.sort_by_key(|(score, _)| (score.fuzzy, -((score.value * 1000.0) as i64)));
+    // The same URI may appear more than once, with different decoded forms;
+    // keep only its best ranked entry.
+    let mut seen = HashSet::with_capacity(scored.len());
+    let ranked = scored
+        .into_iter()
+        .map(|(_, uri)| uri)
+        .filter(|uri| seen.insert(*uri))
+        .collect::<Vec<_>>();
+    match config.grouping {
+        Grouping::None => ranked,
+        Grouping::Parent => group_by_parent(ranked),
+    }
+}
+
+/// Group `ranked` URIs by their parent directory.
+///
+/// Move every URI right behind the previous URI with the same parent, so that
+/// each group takes the place of its best ranked URI, and URIs keep their order
+/// within each group.
+fn group_by_parent(ranked: Vec<&str>) -> Vec<&str> {
+    let mut groups: Vec<Vec<&str>> = Vec::new();
+    let mut group_of_parent = HashMap::new();
+    for uri in ranked {
+        let parent = uri.rsplit_once('/').map_or("", |(parent, _)| parent);
+        let index = *group_of_parent.entry(parent).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[index].push(uri);
+    }
+    groups.into_iter().flatten().collect()
+}
+
+/// Get the number of leading entries in `uris` of the first `count` distinct URIs.
+///
+/// Expect all entries of the same URI, i.e. all its decoded forms, to be adjacent.
+fn entries_of_first_uris(uris: &[(&str, &str)], count: usize) -> usize {
+    let mut distinct = 0;
+    let mut previous = None;
+    for (index, (uri, _)) in uris.iter().enumerate() {
+        if previous != Some(*uri) {
+            if distinct == count {
+                return index;
+            }
+            distinct += 1;
+            previous = Some(*uri);
+        }
+    }
+    uris.len()
+}
+
+/// Find all URIs from `uris` which match all of `terms`, searching recent URIs first.
+///
+/// If `config` has a recent tier, search only the most recent URIs in `uris`
+/// first, i.e. the first [`RecentTier::size`] distinct ones, and return their
+/// matches if there are at least [`RecentTier::min_results`] of them.  All
+/// entries of the same URI must be adjacent in `uris`, see [`entries_of_first_uris`].  Otherwise, or
+/// without a recent tier, search all `uris`, see [`find_matching_uris`].
+pub fn find_matching_uris_tiered<'a, S>(
+    config: ScoringConfig,
+    uris: &[(&'a str, &'a str)],
+    terms: &[S],
+) -> Vec<&'a str>
+where
+    S: AsRef<str> + Debug,
+{
+    if let Some(tier) = config.recent_tier {
+        let tier_end = entries_of_first_uris(uris, tier.size);
+        if tier_end < uris.len() {
+            let recent = find_matching_uris(config, uris[..tier_end].iter().copied(), terms);
+            if tier.min_results <= recent.len() {
+                return recent;
+            }
+            glib::debug!(
+                "Only {} of the {} most recent URIs match, searching all URIs",
+                recent.len(),
+                tier.size
+            );
+        }
+    }
+    find_matching_uris(config, uris.iter().copied(), terms)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Find matches of `terms` in `uris`, with each URI as its own decoded form.
+    fn find<'a>(config: ScoringConfig, uris: &[&'a str], terms: &[&str]) -> Vec<&'a str> {
+        find_matching_uris(config, uris.iter().map(|uri| (*uri, *uri)), terms)
+    }
+
+    #[test]
+    fn segment_starts() {
+        assert!(is_segment_start("/home/me", 0));
+        assert!(is_segment_start("/home/me", 1));
+        assert!(is_segment_start("/home/me", 6));
+        assert!(!is_segment_start("/home/me", 2));
+    }
+
+    #[test]
+    fn segment_prefix_outranks_later_match_within_segment() {
+        let uris = ["/home/me/foo-bar", "/home/me/barfoo"];
+        assert_eq!(
+            find(ScoringConfig::default(), &uris, &["bar"]),
+            ["/home/me/barfoo", "/home/me/foo-bar"]
+        );
+    }
+
+    #[test]
+    fn position_weightings() {
+        assert!((PositionWeighting::Linear.weigh(0.5) - 0.5).abs() < f64::EPSILON);
+        assert!((PositionWeighting::Quadratic.weigh(0.5) - 0.25).abs() < f64::EPSILON);
+        assert!((PositionWeighting::Quadratic.weigh(1.0) - 1.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn quadratic_position_weighting_favours_later_matches_more() {
+        let ratio = |position_weighting| {
+            let config = ScoringConfig {
+                position_weighting,
+                ..ScoringConfig::default()
+            };
+            let late = score_uri(config, "/abcdefgh/xyz", &["xyz"]).unwrap();
+            let early = score_uri(config, "/xyzdefgh/abc", &["xyz"]).unwrap();
+            late.value / early.value
+        };
+        assert!(ratio(PositionWeighting::Linear) < ratio(PositionWeighting::Quadratic));
+    }
+
+    #[test]
+    fn tight_fuzzy_matches_outrank_scattered_ones() {
+        let config = ScoringConfig {
+            fuzzy_gap_penalty: Some(DEFAULT_FUZZY_GAP_PENALTY),
+            ..ScoringConfig::default()
+        };
+        let tight = score_term_fuzzy(config, 1.0, "/home/axbc", "abc").unwrap();
+        let scattered = score_term_fuzzy(config, 1.0, "/home/axxxxbxxxxc", "abc").unwrap();
+        assert!(scattered < tight);
+        assert_eq!(score_term_fuzzy(config, 1.0, "/home/cba", "abc"), None);
+    }
+
+    #[test]
+    fn contiguous_matches_outrank_fuzzy_ones() {
+        let config = ScoringConfig {
+            fuzzy_gap_penalty: Some(DEFAULT_FUZZY_GAP_PENALTY),
+            ..ScoringConfig::default()
+        };
+        let uris = ["/home/me/a-b-c", "/abc/me/project"];
+        assert_eq!(
+            find(config, &uris, &["abc"]),
+            ["/abc/me/project", "/home/me/a-b-c"]
+        );
+        assert_eq!(
+            find(ScoringConfig::default(), &uris, &["abc"]),
+            ["/abc/me/project"]
+        );
+    }
+
+    #[test]
+    fn fuzzy_matches_sort_after_contiguous_matches_with_lower_score() {
+        let config = ScoringConfig {
+            fuzzy_gap_penalty: Some(DEFAULT_FUZZY_GAP_PENALTY),
+            ..ScoringConfig::default()
+        };
+        let fuzzy_uri = "/home/me/axbc";
+        let contiguous_uri = "/abc/me/x/y/z/project";
+        let fuzzy = score_uri(config, fuzzy_uri, &["abc"]).unwrap();
+        let contiguous = score_uri(config, contiguous_uri, &["abc"]).unwrap();
+        assert!(fuzzy.fuzzy);
+        assert!(!contiguous.fuzzy);
+        assert!(contiguous.value < fuzzy.value);
+        assert_eq!(
+            find(config, &[fuzzy_uri, contiguous_uri], &["abc"]),
+            [contiguous_uri, fuzzy_uri]
+        );
+    }
+
+    #[test]
+    fn equal_scores_keep_input_order() {
+        let uris = ["/b/foo", "/c/foo", "/a/foo"];
+        let score = |uri| score_uri(ScoringConfig::default(), uri, &["foo"]).unwrap();
+        assert!(uris.iter().all(|uri| score(uri) == score(uris[0])));
+        assert_eq!(find(ScoringConfig::default(), &uris, &["foo"]), uris);
+        let reversed = ["/a/foo", "/c/foo", "/b/foo"];
+        assert_eq!(
+            find(ScoringConfig::default(), &reversed, &["foo"]),
+            reversed
+        );
+    }
+
+    #[test]
+    fn workspace_extension_does_not_dilute_name_matches() {
+        let with_extension = score_uri(
+            ScoringConfig::default(),
+            &format!("/home/me/acme{WORKSPACE_EXTENSION}"),
+            &["acme"],
+        )
+        .unwrap();
+        let folder = score_uri(ScoringConfig::default(), "/home/me/acme", &["acme"]).unwrap();
+        assert_eq!(with_extension, folder);
+        let uris = ["/home/acme/project", "/home/me/acme.code-workspace"];
+        assert_eq!(
+            find(ScoringConfig::default(), &uris, &["acme"]),
+            ["/home/me/acme.code-workspace", "/home/acme/project"]
+        );
+        assert!(find(ScoringConfig::default(), &uris[1..], &["workspace"]).is_empty());
+    }
+
+    #[test]
+    fn case_sensitive_matching_tells_cases_apart() {
+        let config = ScoringConfig {
+            case_sensitive: true,
+            ..ScoringConfig::default()
+        };
+        let uris = ["/home/me/api", "/home/me/API"];
+        assert_eq!(find(ScoringConfig::default(), &uris, &["API"]), uris);
+        assert_eq!(find(ScoringConfig::default(), &uris, &["api"]), uris);
+        assert_eq!(find(config, &uris, &["API"]), ["/home/me/API"]);
+        assert_eq!(find(config, &uris, &["api"]), ["/home/me/api"]);
+    }
+
+    #[test]
+    fn base_name_parent_and_other_segments_weigh_in_tiers() {
+        let score = |uri| {
+            score_uri(ScoringConfig::default(), uri, &["acme"])
+                .unwrap()
+                .value
+        };
+        let basename = score("/x/y/acme");
+        let parent = score("/x/acme/y");
+        let rest = score("/acme/x/y");
+        assert!(parent < basename);
+        assert!(rest < parent);
+        let weights = SegmentWeights::default();
+        assert!(weights.rest < weights.parent && weights.parent < weights.basename);
+        let uris = ["/acme/x/y", "/x/acme/y", "/x/y/acme"];
+        assert_eq!(
+            find(ScoringConfig::default(), &uris, &["acme"]),
+            ["/x/y/acme", "/x/acme/y", "/acme/x/y"]
+        );
+    }
+
+    #[test]
+    fn word_starts() {
+        let word_start = |uri: &str, index| is_word_start(&uri.to_lowercase(), uri, index);
+        assert!(word_start("/myAwesomeProject", 3));
+        assert!(word_start("/my_awesome_project", 4));
+        assert!(word_start("/my-awesome-project", 4));
+        assert!(!word_start("/myawesomeproject", 3));
+        assert!(!word_start("/MYAWESOME", 3));
+    }
+
+    #[test]
+    fn word_boundaries_boost_camel_snake_and_kebab_case() {
+        let config = ScoringConfig {
+            word_boundaries: true,
+            ..ScoringConfig::default()
+        };
+        for uri in [
+            "/myAwesomeProject",
+            "/my_awesome_project",
+            "/my-awesome-project",
+        ] {
+            let boosted = score_uri(config, uri, &["awesome"]).unwrap();
+            let plain = score_uri(ScoringConfig::default(), uri, &["awesome"]).unwrap();
+            assert!(plain.value < boosted.value, "{uri}");
+        }
+        let uris = ["/home/me/toawesome", "/home/me/myAwesome"];
+        assert_eq!(
+            find(config, &uris, &["awesome"]),
+            ["/home/me/myAwesome", "/home/me/toawesome"]
+        );
+    }
+
+    #[test]
+    fn ignore_separators_matches_all_separator_styles() {
+        let config = ScoringConfig {
+            ignore_separators: true,
+            ..ScoringConfig::default()
+        };
+        let uris = [
+            "/home/me/my-project",
+            "/home/me/my_project",
+            "/home/me/myProject",
+            "/home/me/my project",
+            "/home/me/other",
+        ];
+        assert_eq!(find(config, &uris, &["my project"]), &uris[..4]);
+        assert_eq!(find(config, &uris, &["my-project"]), &uris[..4]);
+        assert_eq!(
+            find(ScoringConfig::default(), &uris, &["my project"]),
+            ["/home/me/my project"]
+        );
+    }
+
+    #[test]
+    fn group_by_parent_clusters_siblings_at_best_rank() {
+        let ranked = vec!["/a/x", "/b/x", "/a/y", "/c/x", "/b/y", "/a/z"];
+        assert_eq!(
+            group_by_parent(ranked),
+            ["/a/x", "/a/y", "/a/z", "/b/x", "/b/y", "/c/x"]
+        );
+    }
+
+    #[test]
+    fn find_matching_uris_groups_by_parent_after_ranking() {
+        let config = ScoringConfig {
+            grouping: Grouping::Parent,
+            ..ScoringConfig::default()
+        };
+        let uris = [
+            "/work/one/foo",
+            "/home/foo",
+            "/work/two/foo",
+            "/work/one/foo-bar",
+        ];
+        let ranked = find(ScoringConfig::default(), &uris, &["foo"]);
+        assert_ne!(ranked, group_by_parent(ranked.clone()));
+        assert_eq!(find(config, &uris, &["foo"]), group_by_parent(ranked));
+    }
+
+    #[test]
+    fn min_term_length_applies_to_longest_term() {
+        let config = ScoringConfig {
+            min_term_length: 2,
+            ..ScoringConfig::default()
+        };
+        let uris = ["/home/me/ab", "/home/me/abc"];
+        assert!(find(config, &uris, &["a"]).is_empty());
+        assert!(find(config, &uris, &["a", "b"]).is_empty());
+        assert_eq!(find(config, &uris, &["ab"]), uris);
+        assert_eq!(find(config, &uris, &["a", "bc"]), ["/home/me/abc"]);
+        assert!(find(config, &uris, &[] as &[&str]).is_empty());
+    }
+
+    #[test]
+    fn entries_of_first_uris_counts_distinct_uris() {
+        let uris = [("a", "/a"), ("a", "/x/a"), ("b", "/b"), ("c", "/c")];
+        assert_eq!(entries_of_first_uris(&uris, 0), 0);
+        assert_eq!(entries_of_first_uris(&uris, 1), 2);
+        assert_eq!(entries_of_first_uris(&uris, 2), 3);
+        assert_eq!(entries_of_first_uris(&uris, 5), 4);
+    }
+
+    #[test]
+    fn recent_tier_covers_workspaces_not_entries() {
+        let uris = [
+            ("a", "/home/a"),
+            ("a", "/home/a-alias"),
+            ("b", "/home/bar"),
+            ("c", "/home/bar-c"),
+        ];
+        let config = ScoringConfig {
+            recent_tier: Some(RecentTier {
+                size: 2,
+                min_results: 1,
+            }),
+            ..ScoringConfig::default()
+        };
+        // With a tier of two workspaces, b is among the most recent ones, so
+        // c never gets searched.
+        assert_eq!(find_matching_uris_tiered(config, &uris, &["bar"]), ["b"]);
+    }
+
+    #[test]
+    fn find_matching_uris_returns_every_uri_once() {
+        let uris = [("a", "/x/foo"), ("a", "/y/foo"), ("b", "/z/foo")];
+        assert_eq!(
+            find_matching_uris(ScoringConfig::default(), uris, &["foo"]),
+            ["a", "b"]
+        );
+    }
+
+    #[test]
+    fn segment_weights_ignore_trailing_slash() {
+        let weights = SegmentWeights::default();
+        assert!((weights.weigh("/a/project/", 3, 1.0) - weights.basename).abs() < f64::EPSILON);
+        assert!((weights.weigh("/a/project", 3, 1.0) - weights.basename).abs() < f64::EPSILON);
+        assert!((weights.weigh("/a/project/", 1, 1.0) - weights.parent).abs() < f64::EPSILON);
+        assert!((weights.weigh("/x/a/project", 1, 1.0) - weights.rest).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn relative_score_threshold_prunes_weak_matches() {
+        let uris = ["/home/me/project", "/project/a/b/c/d/e", "/home/us/project"];
+        let config = ScoringConfig {
+            relative_score_threshold: Some(0.5),
+            ..ScoringConfig::default()
+        };
+        assert_eq!(
+            find(config, &uris, &["project"]),
+            ["/home/me/project", "/home/us/project"]
+        );
+    }
+
+    #[test]
+    fn no_relative_score_threshold_keeps_weak_matches() {
+        let uris = ["/home/me/project", "/project/a/b/c/d/e"];
+        assert_eq!(
+            find(ScoringConfig::default(), &uris, &["project"]),
+            ["/home/me/project", "/project/a/b/c/d/e"]
+        );
+    }
+
+    /// A search over decoded URIs, read from a fixture in `tests/fixtures/scoring/`.
+    struct Fixture {
+        config: ScoringConfig,
+        terms: Vec<String>,
+        uris: Vec<String>,
+    }
+
+    impl Fixture {
+        /// Parse a fixture, see `tests/fixtures/scoring/README.md` for the format.
+        fn parse(contents: &str) -> Self {
+            let (header, uris) = contents.split_once("\n\n").expect("fixture without header");
+            let mut fixture = Self {
+                config: ScoringConfig::default(),
+                terms: Vec::new(),
+                uris: uris.lines().map(ToOwned::to_owned).collect(),
+            };
+            for line in header.lines() {
+                match line.split_once(':').map(|(key, value)| (key, value.trim())) {
+                    Some(("query", query)) => {
+                        fixture.terms = query.split_whitespace().map(ToOwned::to_owned).collect();
+                    }
+                    Some(("fuzzy", "true")) => {
+                        fixture.config.fuzzy_gap_penalty = Some(DEFAULT_FUZZY_GAP_PENALTY);
+                    }
+                    _ => panic!("invalid fixture header {line:?}"),
+                }
+            }
+            fixture
+        }
+
+        /// Rank all matching URIs of this fixture, one per line.
+        fn ranking(&self) -> String {
+            let uris = self.uris.iter().map(String::as_str).collect::<Vec<_>>();
+            let terms = self.terms.iter().map(String::as_str).collect::<Vec<_>>();
+            find(self.config, &uris, &terms)
+                .into_iter()
+                .flat_map(|uri| [uri, "\n"])
+                .collect()
+        }
+    }
+
+    #[test]
+    fn scoring_fixtures_match_expected_rankings() {
+        let directory =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/scoring");
+        let update = std::env::var_os("VSCODE_SEARCH_PROVIDER_UPDATE_FIXTURES").is_some();
+        let mut fixtures = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "txt"))
+            .collect::<Vec<_>>();
+        fixtures.sort();
+        assert!(
+            !fixtures.is_empty(),
+            "no fixtures in {}",
+            directory.display()
+        );
+        for path in fixtures {
+            let ranking = Fixture::parse(&std::fs::read_to_string(&path).unwrap()).ranking();
+            let expected_path = path.with_extension("expected");
+            if update {
+                std::fs::write(&expected_path, &ranking).unwrap();
+            } else {
+                let expected = std::fs::read_to_string(&expected_path).unwrap_or_default();
+                assert_eq!(
+                    ranking,
+                    expected,
+                    "ranking of {} changed, see tests/fixtures/scoring/README.md",
+                    path.display()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at http://mozilla.org/MPL/2.0/.
+
+//! Expand user-supplied paths, and look up XDG directories.
+
+use std::path::{Path, PathBuf};
+
+/// Whether `c` may appear in the name of an environment variable.
+fn is_variable_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Expand all environment variable references in `text`.
+///
+/// Expand `$VAR` and `${VAR}`, where `VAR` consists of ASCII alphanumeric
+/// characters and `_`.  Leave references to unset variables, and all other `$`
+/// characters, as they are.
+fn expand_variables(text: &str) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(index) = rest.find('$') {
+        expanded.push_str(&rest[..index]);
+        let reference = &rest[index..];
+        let (name, length) = if let Some(braced) = reference[1..].strip_prefix('{') {
+            // Skip `${`, the name, and `}`
+            braced
+                .find('}')
+                .map_or(("", 1), |end| (&braced[..end], end + 3))
+        } else {
+            let end = reference[1..]
+                .find(|c| !is_variable_name_char(c))
+                .unwrap_or(reference.len() - 1);
+            (&reference[1..=end], end + 1)
+        };
+        let value = Some(name)
+            .filter(|name| !name.is_empty() && name.chars().all(is_variable_name_char))
+            .and_then(|name| std::env::var(name).ok());
+        match value {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&reference[..length]),
+        }
+        rest = &reference[length..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Expand a user-supplied `path`.
+///
+/// Expand a leading `~`, i.e. `~` on its own or followed by `/`, to the home
+/// directory, and then all environment variable references, i.e. `$VAR` and
+/// `${VAR}`.  Leave `~` elsewhere, `~user`, and references to unset variables
+/// as they are.
+pub fn expand_path(path: &str) -> PathBuf {
+    let expanded = expand_variables(path);
+    if expanded == "~" {
+        glib::home_dir()
+    } else if let Some(rest) = expanded.strip_prefix("~/") {
+        glib::home_dir().join(rest)
+    } else {
+        PathBuf::from(expanded)
+    }
+}
+
+/// Get a path from the environment variable `name`, if set.
+///
+/// Expand the path with [`expand_path`], unless it is not valid UTF-8.
+pub fn path_from_environment(name: &str) -> Option<PathBuf> {
+    let value = std::env::var_os(name)?;
+    Some(
+        value
+            .to_str()
+            .map_or_else(|| PathBuf::from(&value), expand_path),
+    )
+}
+
+/// Get the XDG state directory, i.e. `$XDG_STATE_HOME`.
+pub fn state_home() -> PathBuf {
+    glib::user_state_dir()
+}
+
+/// Get all XDG data directories, i.e. `$XDG_DATA_HOME` followed by all of
+/// `$XDG_DATA_DIRS`, in order of precedence.
+///
+/// Skip directories which do not exist, and all but the first occurrence of
+/// directories listed more than once, to avoid redundant lookups with a
+/// misconfigured `$XDG_DATA_DIRS`.
+pub fn data_dirs() -> Vec<PathBuf> {
+    unique_existing_dirs(std::iter::once(glib::user_data_dir()).chain(glib::system_data_dirs()))
+}
+
+/// Get the first occurrence of every directory in `dirs` which exists, in order.
+fn unique_existing_dirs(dirs: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+    let mut unique: Vec<PathBuf> = Vec::new();
+    for dir in dirs {
+        if dir.is_dir() && !unique.contains(&dir) {
+            unique.push(dir);
+        }
+    }
+    unique
+}
+
+/// The extensions of icon files in icon themes.
+const ICON_EXTENSIONS: [&str; 3] = ["png", "svg", "xpm"];
+
+/// Whether any of `data_dirs` has an icon `name` in the hicolor theme.
+///
+/// Look for `icons/hicolor/<size>/apps/<name>.<extension>` in every data
+/// directory, for all sizes, as well as for legacy icons in `pixmaps`, which
+/// icon themes fall back to as well.
+pub fn has_hicolor_icon(data_dirs: &[PathBuf], name: &str) -> bool {
+    let has_icon_file = |directory: &Path| {
+        ICON_EXTENSIONS
+            .iter()
+            .any(|extension| directory.join(format!("{name}.{extension}")).is_file())
+    };
+    data_dirs.iter().any(|data_dir| {
+        has_icon_file(&data_dir.join("pixmaps"))
+            || std::fs::read_dir(data_dir.join("icons").join("hicolor")).is_ok_and(|sizes| {
+                sizes
+                    .filter_map(Result::ok)
+                    .any(|size| has_icon_file(&size.path().join("apps")))
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn home() -> String {
+        std::env::var("HOME").expect("tests need $HOME")
+    }
+
+    #[test]
+    fn expand_path_expands_leading_tilde() {
+        assert_eq!(expand_path("~"), glib::home_dir());
+        assert_eq!(expand_path("~/sub"), glib::home_dir().join("sub"));
+        assert_eq!(expand_path("~user/sub"), PathBuf::from("~user/sub"));
+        assert_eq!(expand_path("/srv/~/sub"), PathBuf::from("/srv/~/sub"));
+    }
+
+    #[test]
+    fn expand_path_expands_variables() {
+        assert_eq!(expand_path("$HOME/sub"), Path::new(&home()).join("sub"));
+        assert_eq!(
+            expand_path("${HOME}sub"),
+            PathBuf::from(format!("{}sub", home()))
+        );
+        assert_eq!(
+            expand_path("/srv/$VSCODE_SEARCH_PROVIDER_UNSET_TEST_VARIABLE/${}/$"),
+            PathBuf::from("/srv/$VSCODE_SEARCH_PROVIDER_UNSET_TEST_VARIABLE/${}/$")
+        );
+    }
+
+    fn test_directory(test: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!(
+            "gnome-search-providers-vscode-xdg-{}-{test}",
+            std::process::id()
+        ));
+        if directory.exists() {
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    #[test]
+    fn unique_existing_dirs_skips_duplicates_and_missing_dirs() {
+        let directory = test_directory("data-dirs");
+        let (a, b) = (directory.join("a"), directory.join("b"));
+        std::fs::create_dir(&a).unwrap();
+        std::fs::create_dir(&b).unwrap();
+        let dirs = [
+            b.clone(),
+            directory.join("missing"),
+            a.clone(),
+            b.clone(),
+            a.clone(),
+        ];
+        assert_eq!(unique_existing_dirs(dirs), [b, a]);
+    }
+
+    #[test]
+    fn has_hicolor_icon_in_apps_and_pixmaps() {
+        let directory = test_directory("hicolor");
+        let apps = directory.join("icons/hicolor/48x48/apps");
+        std::fs::create_dir_all(&apps).unwrap();
+        std::fs::write(apps.join("code.png"), "").unwrap();
+        std::fs::create_dir_all(directory.join("pixmaps")).unwrap();
+        std::fs::write(directory.join("pixmaps/codium.xpm"), "").unwrap();
+        let data_dirs = [directory.join("missing"), directory];
+        assert!(has_hicolor_icon(&data_dirs, "code"));
+        assert!(has_hicolor_icon(&data_dirs, "codium"));
+        assert!(!has_hicolor_icon(&data_dirs, "code-oss"));
+    }
+
+    #[test]
+    fn expand_path_keeps_literal_paths() {
+        assert_eq!(expand_path("/srv/projects"), PathBuf::from("/srv/projects"));
+        assert_eq!(expand_path("projects"), PathBuf::from("projects"));
+    }
+}